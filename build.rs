@@ -1,42 +1,152 @@
 // Based on https://github.com/rust-lang/libz-sys/blob/1.0.25/build.rs
 
 use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
+/// API levels selectable through the `0_5`/.../`1_5` features, oldest first.
+/// Each level is exposed to the crate as a `libwebp_<major>_<minor>` cfg.
+const API_LEVELS: &[(u32, u32)] = &[(0, 5), (0, 6), (1, 1), (1, 2), (1, 3), (1, 4), (1, 5)];
+
+/// Version of the bundled libwebp in c_src.
+const BUNDLED_VERSION: (u32, u32) = (1, 2);
+
+/// Environment variables that influence the build.
+const ENV_VARS: &[&str] = &[
+    "LIBWEBP_SYS_STATIC",
+    "LIBWEBP_SYS_SOURCE_DIR",
+    "LIBWEBP_SYS_LIB_DIR",
+    "LIBWEBP_SYS_INCLUDE_DIR",
+    "LIBWEBP_SYS_NO_PKG_CONFIG",
+    "LIBWEBP_SYS_MAX_ALLOCABLE_MEMORY",
+    "__LIBWEBP_SYS_FORBID_BUILD",
+];
+
 fn main() {
-    println!("cargo:rerun-if-env-changed=LIBWEBP_SYS_STATIC");
+    for var in ENV_VARS {
+        println!("cargo:rerun-if-env-changed={}", var);
+    }
     println!("cargo:rerun-if-changed=build.rs");
+    for &(major, minor) in API_LEVELS {
+        println!("cargo:rustc-check-cfg=cfg(libwebp_{}_{})", major, minor);
+    }
+    println!("cargo:rustc-check-cfg=cfg(libwebp_threads)");
     let host = env::var("HOST").unwrap();
     let target = env::var("TARGET").unwrap();
     let host_and_target_contain = |s| host.contains(s) && target.contains(s);
 
+    // Documentation builds (docs.rs, the CI doc job) link nothing, so the
+    // bundled source or an older system libwebp must not cap the documented
+    // API level.
+    if cfg!(feature = "__doc_cfg") {
+        emit_api_level(None, "__doc_cfg");
+        return;
+    }
+
+    // Resolved at runtime by src/dynamic.rs.
+    if cfg!(feature = "dynamic") {
+        Metadata::default().emit("dynamic loading");
+        return;
+    }
+
+    // There is no system libwebp to probe for on WebAssembly.
+    if target.starts_with("wasm32") || env::var_os("LIBWEBP_SYS_SOURCE_DIR").is_some() {
+        return build_libwebp();
+    }
+
+    let want_static =
+        cfg!(feature = "static") || env::var("LIBWEBP_SYS_STATIC").unwrap_or_default() == "1";
+
+    if let Some(lib_dir) = env::var_os("LIBWEBP_SYS_LIB_DIR") {
+        if let Some(include_dir) = env::var_os("LIBWEBP_SYS_INCLUDE_DIR") {
+            let header = Path::new(&include_dir).join("webp/decode.h");
+            if !header.exists() {
+                panic!("LIBWEBP_SYS_INCLUDE_DIR: {} not found", header.display());
+            }
+        }
+        println!(
+            "cargo:rustc-link-search=native={}",
+            Path::new(&lib_dir).display()
+        );
+        // A static libwebp 1.3.0 or later needs libsharpyuv even if we don't
+        // use it directly.
+        let lib_dir = Path::new(&lib_dir);
+        let sharpyuv = cfg!(feature = "sharpyuv")
+            || (want_static
+                && (lib_dir.join("libsharpyuv.a").exists()
+                    || lib_dir.join("sharpyuv.lib").exists()));
+        link_libraries(if want_static { "static=" } else { "" }, sharpyuv);
+        let root = lib_dir.parent().map(Path::to_path_buf);
+        let include = match env::var_os("LIBWEBP_SYS_INCLUDE_DIR") {
+            Some(include_dir) => Some(PathBuf::from(include_dir)),
+            None => root
+                .as_ref()
+                .map(|root| root.join("include"))
+                .filter(|include| include.join("webp/decode.h").exists()),
+        };
+        // Without headers there is nothing to tell the version from, and the
+        // default 0.4 layout would be too small for a newer library.
+        if include.is_none() && requested_api_level().is_none() {
+            panic!(
+                "LIBWEBP_SYS_LIB_DIR: the libwebp version is unknown; set \
+                 LIBWEBP_SYS_INCLUDE_DIR or select an API level feature such as `1_2`"
+            );
+        }
+        Metadata {
+            root,
+            include: include.into_iter().collect(),
+            version: None,
+            statik: want_static,
+        }
+        .emit("LIBWEBP_SYS_LIB_DIR");
+        return;
+    }
+
     // Don't run pkg-config if we're linking statically (we'll build below) and
     // also don't run pkg-config on macOS/FreeBSD/DragonFly. That'll end up printing
     // `-L /usr/lib` which wreaks havoc with linking to an OpenSSL in /usr/local/lib
     // (Homebrew, Ports, etc.)
-    let want_static =
-        cfg!(feature = "static") || env::var("LIBWEBP_SYS_STATIC").unwrap_or(String::new()) == "1";
-    if !want_static &&
-       !target.contains("msvc") && // pkg-config just never works here
-       !(host_and_target_contain("apple") ||
+    let no_pkg_config = env::var("LIBWEBP_SYS_NO_PKG_CONFIG").unwrap_or_default() == "1";
+    if !(want_static ||
+         no_pkg_config ||
+         target.contains("msvc") || // pkg-config just never works here
+         host_and_target_contain("apple") ||
          host_and_target_contain("freebsd") ||
          host_and_target_contain("dragonfly"))
     {
+        // Same rules as the pkg-config crate, so that we can report it.
+        let statik = env::var_os("LIBWEBP_STATIC").is_some()
+            || (env::var_os("LIBWEBP_DYNAMIC").is_none()
+                && env::var_os("PKG_CONFIG_ALL_STATIC").is_some());
         let mut config = pkg_config::Config::new();
-        config.cargo_metadata(true);
-        if config.probe("libwebp").is_ok() {
-            if cfg!(feature = "demux") {
-                config.probe("libwebpdemux").unwrap();
+        config.cargo_metadata(true).statik(statik);
+        if let Ok(library) = config.probe("libwebp") {
+            for &(package, _) in &extra_libraries() {
+                if let Err(e) = config.probe(package) {
+                    panic!(
+                        "libwebp was found by pkg-config, but {} was not: {}",
+                        package, e
+                    );
+                }
             }
-            if cfg!(feature = "mux") {
-                config.probe("libwebpmux").unwrap();
+            Metadata {
+                root: pkg_config::get_variable("libwebp", "prefix")
+                    .ok()
+                    .map(PathBuf::from),
+                include: library.include_paths,
+                version: Some(library.version),
+                statik,
             }
+            .emit("pkg-config");
             return;
         }
     }
 
     if target.contains("msvc") {
-        if try_vcpkg() {
+        if let Some(metadata) = try_vcpkg() {
+            metadata.emit("vcpkg");
             return;
         }
     }
@@ -62,19 +172,348 @@ fn main() {
     // In any case test if zlib is actually installed and if so we link to it,
     // otherwise continue below to build things.
     if libwebp_installed() {
-        println!("cargo:rustc-link-lib=webp");
-        if cfg!(feature = "demux") {
-            println!("cargo:rustc-link-lib=webpdemux");
-        }
-        if cfg!(feature = "mux") {
-            println!("cargo:rustc-link-lib=webpmux");
-        }
+        link_libraries("", cfg!(feature = "sharpyuv"));
+        system_metadata(no_pkg_config).emit("the system");
         return;
     }
 
     build_libwebp()
 }
 
+/// The libraries linked in addition to libwebp for the selected features, as
+/// `(pkg-config/vcpkg package, library name)`.
+fn extra_libraries() -> Vec<(&'static str, &'static str)> {
+    let mut libraries = Vec::new();
+    if cfg!(feature = "demux") {
+        libraries.push(("libwebpdemux", "webpdemux"));
+    }
+    if cfg!(feature = "mux") {
+        libraries.push(("libwebpmux", "webpmux"));
+    }
+    if cfg!(feature = "sharpyuv") {
+        libraries.push(("libsharpyuv", "sharpyuv"));
+    }
+    libraries
+}
+
+/// Links libwebp and the libraries of the selected features, dependents
+/// first so that static linking works. `kind` is a `rustc-link-lib` kind
+/// prefix such as `static=`.
+fn link_libraries(kind: &str, sharpyuv: bool) {
+    if cfg!(feature = "demux") {
+        println!("cargo:rustc-link-lib={}webpdemux", kind);
+    }
+    if cfg!(feature = "mux") {
+        println!("cargo:rustc-link-lib={}webpmux", kind);
+    }
+    println!("cargo:rustc-link-lib={}webp", kind);
+    if sharpyuv {
+        println!("cargo:rustc-link-lib={}sharpyuv", kind);
+    }
+}
+
+/// Link metadata exported to the build scripts of dependents as
+/// `DEP_WEBP_ROOT`, `DEP_WEBP_INCLUDE`, `DEP_WEBP_VERSION` and `DEP_WEBP_STATIC`.
+/// Unknown values are left out.
+#[derive(Default)]
+struct Metadata {
+    /// Installation prefix.
+    root: Option<PathBuf>,
+    /// Directories containing `webp/decode.h` etc., joined with the platform's
+    /// path separator.
+    include: Vec<PathBuf>,
+    /// Version of the linked libwebp.
+    version: Option<String>,
+    /// Whether libwebp is linked statically.
+    statik: bool,
+}
+
+impl Metadata {
+    /// Prints the metadata and turns on the API level of the linked library.
+    fn emit(self, origin: &str) {
+        if let Some(ref root) = self.root {
+            println!("cargo:root={}", root.display());
+        }
+        if !self.include.is_empty() {
+            let include = env::join_paths(&self.include).unwrap();
+            println!("cargo:include={}", Path::new(&include).display());
+        }
+        if let Some(ref version) = self.version {
+            println!("cargo:version={}", version);
+        }
+        println!("cargo:static={}", if self.statik { 1 } else { 0 });
+        let found = match self.version {
+            Some(ref version) => parse_version(version),
+            None => header_api_level(&self.include),
+        };
+        emit_api_level(found, origin);
+    }
+}
+
+/// Turns on the `libwebp_*` cfgs up to the API level of the linked library.
+///
+/// `found` is the version of the linked library, if known. Otherwise we trust
+/// the features the user selected.
+fn emit_api_level(found: Option<(u32, u32)>, origin: &str) {
+    let requested = requested_api_level();
+    if let (Some(found), Some(requested)) = (found, requested) {
+        if requested > found {
+            panic!(
+                "the `{}_{}` feature requires libwebp {}.{}, but libwebp {}.{} was found from {}",
+                requested.0, requested.1, requested.0, requested.1, found.0, found.1, origin,
+            );
+        }
+    }
+    if let Some(level) = found.or(requested) {
+        for &api_level in API_LEVELS {
+            if level >= api_level {
+                println!("cargo:rustc-cfg=libwebp_{}_{}", api_level.0, api_level.1);
+            }
+        }
+    }
+}
+
+/// The newest API level selected through the features.
+fn requested_api_level() -> Option<(u32, u32)> {
+    API_LEVELS
+        .iter()
+        .rev()
+        .cloned()
+        .find(|&(major, minor)| env::var_os(format!("CARGO_FEATURE_{}_{}", major, minor)).is_some())
+}
+
+/// Tells the API level of installed headers from their ABI versions, which
+/// only change with some releases, and from declarations added in between.
+fn header_api_level(include: &[PathBuf]) -> Option<(u32, u32)> {
+    let dir = include
+        .iter()
+        .find(|dir| dir.join("webp/encode.h").exists())?;
+    let encode = fs::read_to_string(dir.join("webp/encode.h")).ok()?;
+    let decode = fs::read_to_string(dir.join("webp/decode.h")).ok()?;
+    let abi_version = |header: &str, name: &str| {
+        header.lines().find_map(|line| {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next(), words.next()) {
+                (Some("#define"), Some(n), Some(value)) if n == name => {
+                    u32::from_str_radix(value.trim_start_matches("0x"), 16).ok()
+                }
+                _ => None,
+            }
+        })
+    };
+    let encoder = abi_version(&encode, "WEBP_ENCODER_ABI_VERSION")?;
+    let decoder = abi_version(&decode, "WEBP_DECODER_ABI_VERSION")?;
+    let levels = [
+        (encoder >= 0x0209 || decoder >= 0x0208, (0, 5)),
+        (encoder >= 0x020E, (0, 6)),
+        (encoder >= 0x020F || decoder >= 0x0209, (1, 1)),
+        (encode.contains(" qmin;"), (1, 2)),
+        (dir.join("webp/sharpyuv/sharpyuv.h").exists(), (1, 3)),
+        (decoder >= 0x0210, (1, 4)),
+        (encoder >= 0x0210, (1, 5)),
+    ];
+    Some(
+        levels
+            .iter()
+            .filter(|&&(present, _)| present)
+            .map(|&(_, level)| level)
+            .max()
+            .unwrap_or((0, 4)),
+    )
+}
+
+/// Parses the major and minor components of a version such as `1.2.0`.
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let mut components = version.split('.').map(|c| c.parse::<u32>());
+    match (components.next(), components.next()) {
+        (Some(Ok(major)), Some(Ok(minor))) => Some((major, minor)),
+        _ => None,
+    }
+}
+
+/// Directories of the libwebp source tree, relative to its root, whose `.c`
+/// files make up each library.
+const LIBWEBP_DIRS: &[&str] = &["src/dec", "src/enc", "src/dsp", "src/utils"];
+const LIBWEBPDEMUX_DIRS: &[&str] = &["src/demux"];
+const LIBWEBPMUX_DIRS: &[&str] = &["src/mux"];
+const LIBSHARPYUV_DIRS: &[&str] = &["sharpyuv"];
+
+/// Lists the C sources of a library. Scanning the directories rather than
+/// naming the files keeps `LIBWEBP_SYS_SOURCE_DIR` working across releases.
+fn c_sources(root: &Path, dirs: &[&str]) -> Vec<PathBuf> {
+    let mut sources = Vec::new();
+    for dir in dirs {
+        let dir = root.join(dir);
+        let entries = fs::read_dir(&dir)
+            .unwrap_or_else(|e| panic!("failed to read {}: {}", dir.display(), e));
+        for entry in entries {
+            let path = entry.unwrap().path();
+            if path.extension() == Some(OsStr::new("c")) {
+                sources.push(path);
+            }
+        }
+    }
+    sources.sort();
+    sources
+}
+
+/// Reads the version of a libwebp source tree from `src/dec/vp8i_dec.h`.
+fn source_version(root: &Path) -> Option<String> {
+    let header = fs::read_to_string(root.join("src/dec/vp8i_dec.h")).ok()?;
+    let define = |name: &str| {
+        header.lines().find_map(|line| {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next(), words.next()) {
+                (Some("#define"), Some(n), Some(value)) if n == name => value.parse::<u32>().ok(),
+                _ => None,
+            }
+        })
+    };
+    Some(format!(
+        "{}.{}.{}",
+        define("DEC_MAJ_VERSION")?,
+        define("DEC_MIN_VERSION")?,
+        define("DEC_REV_VERSION")?
+    ))
+}
+
+/// SIMD extensions libwebp has separate source files for. The files are named
+/// after the extension (e.g. `dec_sse41.c`) and need target-specific flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SimdExt {
+    Sse2,
+    Sse41,
+    Neon,
+    Msa,
+}
+
+impl SimdExt {
+    fn of_source(path: &Path) -> Option<SimdExt> {
+        let path = path.to_str()?;
+        if path.ends_with("_sse2.c") {
+            Some(SimdExt::Sse2)
+        } else if path.ends_with("_sse41.c") {
+            Some(SimdExt::Sse41)
+        } else if path.ends_with("_neon.c") {
+            Some(SimdExt::Neon)
+        } else if path.ends_with("_msa.c") {
+            Some(SimdExt::Msa)
+        } else {
+            None
+        }
+    }
+}
+
+/// SIMD extensions enabled for the bundled build, and how to compile them.
+struct Simd {
+    /// Enabled extensions, with the flags their sources are compiled with.
+    enabled: Vec<(SimdExt, Vec<&'static str>)>,
+    /// Flags applied to every source (the generic code needs them too in order
+    /// to see the extension's intrinsics, e.g. MSA).
+    common_flags: Vec<&'static str>,
+}
+
+impl Simd {
+    fn for_target() -> Simd {
+        let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+        let target_features = env::var("CARGO_CFG_TARGET_FEATURE").unwrap_or_default();
+        let has_feature = |name| target_features.split(',').any(|f| f == name);
+        // MSVC enables the x86 intrinsics without flags.
+        let gnu_like = env::var("CARGO_CFG_TARGET_ENV").unwrap_or_default() != "msvc";
+
+        let mut enabled = Vec::new();
+        let mut common_flags = Vec::new();
+        match arch.as_str() {
+            "x86" | "x86_64" => {
+                // SSE2 is part of the baseline on every x86_64 and i686 target.
+                // SSE4.1 code paths are selected at runtime by libwebp's CPU
+                // detection, so they are always compiled in.
+                if has_feature("sse2") {
+                    enabled.push((
+                        SimdExt::Sse2,
+                        if gnu_like { vec!["-msse2"] } else { vec![] },
+                    ));
+                }
+                enabled.push((
+                    SimdExt::Sse41,
+                    if gnu_like { vec!["-msse4.1"] } else { vec![] },
+                ));
+            }
+            "aarch64" => enabled.push((SimdExt::Neon, vec![])),
+            "arm" if has_feature("neon") => enabled.push((
+                SimdExt::Neon,
+                if gnu_like { vec!["-mfpu=neon"] } else { vec![] },
+            )),
+            "mips" | "mips64" if has_feature("msa") => {
+                enabled.push((SimdExt::Msa, vec![]));
+                common_flags.push("-mmsa");
+            }
+            _ => {}
+        }
+        Simd {
+            enabled,
+            common_flags,
+        }
+    }
+
+    /// Defines libwebp's `WEBP_HAVE_*` macros so that the generic code
+    /// dispatches to the SIMD implementations.
+    fn define_macros(&self, build: &mut cc::Build) {
+        for &(ext, _) in &self.enabled {
+            match ext {
+                SimdExt::Sse2 => build.define("WEBP_HAVE_SSE2", None),
+                SimdExt::Sse41 => build.define("WEBP_HAVE_SSE41", None),
+                SimdExt::Neon => build.define("WEBP_HAVE_NEON", None),
+                // There is no WEBP_HAVE_MSA: dsp.h keys off `__mips_msa`.
+                SimdExt::Msa => build,
+            };
+        }
+        for flag in &self.common_flags {
+            build.flag(flag);
+        }
+    }
+
+    fn flags(&self, ext: SimdExt) -> Option<&[&'static str]> {
+        self.enabled
+            .iter()
+            .find(|&&(enabled, _)| enabled == ext)
+            .map(|(_, flags)| &flags[..])
+    }
+}
+
+/// Compiles `sources` into a static library. SIMD sources are compiled as
+/// separate objects with their own flags, and skipped when the extension is
+/// not available for the target.
+fn compile_with_simd(base: &cc::Build, simd: &Simd, sources: &[PathBuf], name: &str) {
+    let mut library = base.clone();
+    let mut simd_sources: Vec<(SimdExt, Vec<&Path>)> = Vec::new();
+    for source in sources {
+        match SimdExt::of_source(source) {
+            None => {
+                library.file(source);
+            }
+            Some(ext) => {
+                if simd.flags(ext).is_none() {
+                    continue;
+                }
+                match simd_sources.iter_mut().find(|(e, _)| *e == ext) {
+                    Some((_, files)) => files.push(source),
+                    None => simd_sources.push((ext, vec![source])),
+                }
+            }
+        }
+    }
+    for (ext, files) in simd_sources {
+        let mut build = base.clone();
+        build.files(files);
+        for flag in simd.flags(ext).unwrap() {
+            build.flag(flag);
+        }
+        library.objects(build.compile_intermediates());
+    }
+    library.compile(name);
+}
+
 fn build_libwebp() {
     // For testing purpose
     if let Ok(value) = std::env::var("__LIBWEBP_SYS_FORBID_BUILD") {
@@ -82,154 +521,190 @@ fn build_libwebp() {
             panic!("__LIBWEBP_SYS_FORBID_BUILD is set to 1");
         }
     }
-    cc::Build::new()
-        .file("c_src/src/dec/alpha_dec.c")
-        .file("c_src/src/dec/buffer_dec.c")
-        .file("c_src/src/dec/frame_dec.c")
-        .file("c_src/src/dec/idec_dec.c")
-        .file("c_src/src/dec/io_dec.c")
-        .file("c_src/src/dec/quant_dec.c")
-        .file("c_src/src/dec/tree_dec.c")
-        .file("c_src/src/dec/vp8_dec.c")
-        .file("c_src/src/dec/vp8l_dec.c")
-        .file("c_src/src/dec/webp_dec.c")
-        .file("c_src/src/enc/alpha_enc.c")
-        .file("c_src/src/enc/analysis_enc.c")
-        .file("c_src/src/enc/backward_references_cost_enc.c")
-        .file("c_src/src/enc/backward_references_enc.c")
-        .file("c_src/src/enc/config_enc.c")
-        .file("c_src/src/enc/cost_enc.c")
-        .file("c_src/src/enc/filter_enc.c")
-        .file("c_src/src/enc/frame_enc.c")
-        .file("c_src/src/enc/histogram_enc.c")
-        .file("c_src/src/enc/iterator_enc.c")
-        .file("c_src/src/enc/near_lossless_enc.c")
-        .file("c_src/src/enc/picture_enc.c")
-        .file("c_src/src/enc/picture_csp_enc.c")
-        .file("c_src/src/enc/picture_psnr_enc.c")
-        .file("c_src/src/enc/picture_rescale_enc.c")
-        .file("c_src/src/enc/picture_tools_enc.c")
-        .file("c_src/src/enc/predictor_enc.c")
-        .file("c_src/src/enc/quant_enc.c")
-        .file("c_src/src/enc/syntax_enc.c")
-        .file("c_src/src/enc/token_enc.c")
-        .file("c_src/src/enc/tree_enc.c")
-        .file("c_src/src/enc/vp8l_enc.c")
-        .file("c_src/src/enc/webp_enc.c")
-        .file("c_src/src/dsp/alpha_processing.c")
-        .file("c_src/src/dsp/alpha_processing_mips_dsp_r2.c")
-        .file("c_src/src/dsp/cpu.c")
-        .file("c_src/src/dsp/dec.c")
-        .file("c_src/src/dsp/dec_clip_tables.c")
-        .file("c_src/src/dsp/dec_mips32.c")
-        .file("c_src/src/dsp/dec_mips_dsp_r2.c")
-        .file("c_src/src/dsp/filters.c")
-        .file("c_src/src/dsp/filters_mips_dsp_r2.c")
-        .file("c_src/src/dsp/lossless.c")
-        .file("c_src/src/dsp/lossless_mips_dsp_r2.c")
-        .file("c_src/src/dsp/rescaler.c")
-        .file("c_src/src/dsp/rescaler_mips32.c")
-        .file("c_src/src/dsp/rescaler_mips_dsp_r2.c")
-        .file("c_src/src/dsp/upsampling.c")
-        .file("c_src/src/dsp/upsampling_mips_dsp_r2.c")
-        .file("c_src/src/dsp/yuv.c")
-        .file("c_src/src/dsp/yuv_mips32.c")
-        .file("c_src/src/dsp/yuv_mips_dsp_r2.c")
-        .file("c_src/src/dsp/cost.c")
-        .file("c_src/src/dsp/cost_mips32.c")
-        .file("c_src/src/dsp/cost_mips_dsp_r2.c")
-        .file("c_src/src/dsp/cost_neon.c")
-        .file("c_src/src/dsp/enc.c")
-        .file("c_src/src/dsp/enc_mips32.c")
-        .file("c_src/src/dsp/enc_mips_dsp_r2.c")
-        .file("c_src/src/dsp/lossless_enc.c")
-        .file("c_src/src/dsp/lossless_enc_mips32.c")
-        .file("c_src/src/dsp/lossless_enc_mips_dsp_r2.c")
-        .file("c_src/src/dsp/ssim.c")
-        .file("c_src/src/dsp/alpha_processing_sse41.c")
-        .file("c_src/src/dsp/dec_sse41.c")
-        .file("c_src/src/dsp/upsampling_sse41.c")
-        .file("c_src/src/dsp/yuv_sse41.c")
-        .file("c_src/src/dsp/alpha_processing_sse2.c")
-        .file("c_src/src/dsp/dec_sse2.c")
-        .file("c_src/src/dsp/filters_sse2.c")
-        .file("c_src/src/dsp/lossless_sse2.c")
-        .file("c_src/src/dsp/rescaler_sse2.c")
-        .file("c_src/src/dsp/upsampling_sse2.c")
-        .file("c_src/src/dsp/yuv_sse2.c")
-        .file("c_src/src/dsp/alpha_processing_neon.c")
-        .file("c_src/src/dsp/dec_neon.c")
-        .file("c_src/src/dsp/filters_neon.c")
-        .file("c_src/src/dsp/lossless_neon.c")
-        .file("c_src/src/dsp/rescaler_neon.c")
-        .file("c_src/src/dsp/upsampling_neon.c")
-        .file("c_src/src/dsp/yuv_neon.c")
-        .file("c_src/src/dsp/dec_msa.c")
-        .file("c_src/src/dsp/filters_msa.c")
-        .file("c_src/src/dsp/lossless_msa.c")
-        .file("c_src/src/dsp/rescaler_msa.c")
-        .file("c_src/src/dsp/upsampling_msa.c")
-        .file("c_src/src/dsp/cost_sse2.c")
-        .file("c_src/src/dsp/enc_sse2.c")
-        .file("c_src/src/dsp/lossless_enc_sse2.c")
-        .file("c_src/src/dsp/ssim_sse2.c")
-        .file("c_src/src/dsp/enc_sse41.c")
-        .file("c_src/src/dsp/lossless_enc_sse41.c")
-        .file("c_src/src/dsp/enc_neon.c")
-        .file("c_src/src/dsp/lossless_enc_neon.c")
-        .file("c_src/src/dsp/enc_msa.c")
-        .file("c_src/src/dsp/lossless_enc_msa.c")
-        .file("c_src/src/utils/bit_reader_utils.c")
-        .file("c_src/src/utils/color_cache_utils.c")
-        .file("c_src/src/utils/filters_utils.c")
-        .file("c_src/src/utils/huffman_utils.c")
-        .file("c_src/src/utils/quant_levels_dec_utils.c")
-        .file("c_src/src/utils/rescaler_utils.c")
-        .file("c_src/src/utils/random_utils.c")
-        .file("c_src/src/utils/thread_utils.c")
-        .file("c_src/src/utils/utils.c")
-        .file("c_src/src/utils/bit_writer_utils.c")
-        .file("c_src/src/utils/huffman_encode_utils.c")
-        .file("c_src/src/utils/quant_levels_utils.c")
-        .include("c_src")
-        .compile("webp");
+    let (root, version, origin) = match env::var_os("LIBWEBP_SYS_SOURCE_DIR") {
+        Some(dir) => {
+            let root = PathBuf::from(dir);
+            println!("cargo:rerun-if-changed={}", root.display());
+            let version = source_version(&root);
+            (root, version, "LIBWEBP_SYS_SOURCE_DIR")
+        }
+        None => {
+            let root = PathBuf::from("c_src");
+            let version = source_version(&root)
+                .or_else(|| Some(format!("{}.{}", BUNDLED_VERSION.0, BUNDLED_VERSION.1)));
+            (root, version, "the bundled source")
+        }
+    };
+
+    let simd = Simd::for_target();
+    let mut base = cc::Build::new();
+    base.include(&root);
+    if target_is_wasm_unknown() {
+        // No C library: use the headers in wasm-shim, backed by
+        // src/wasm_shim.rs.
+        println!("cargo:rerun-if-changed=wasm-shim");
+        base.include("wasm-shim");
+    }
+    simd.define_macros(&mut base);
+    let threads = cfg!(feature = "threads") && target_has_threads();
+    if threads {
+        base.define("WEBP_USE_THREAD", None);
+    }
+    if let Ok(value) = env::var("LIBWEBP_SYS_MAX_ALLOCABLE_MEMORY") {
+        // Caps every single allocation of libwebp (WebPSafeMalloc etc.).
+        let max: u64 = value
+            .trim()
+            .parse()
+            .expect("LIBWEBP_SYS_MAX_ALLOCABLE_MEMORY must be a number of bytes");
+        base.define("WEBP_MAX_ALLOCABLE_MEMORY", format!("{}ULL", max).as_str());
+    }
+
+    let compile = |dirs, name| compile_with_simd(&base, &simd, &c_sources(&root, dirs), name);
+    compile(LIBWEBP_DIRS, "webp");
     if cfg!(feature = "demux") {
-        cc::Build::new()
-            .file("c_src/src/demux/anim_decode.c")
-            .file("c_src/src/demux/demux.c")
-            .include("c_src")
-            .compile("webpdemux");
+        compile(LIBWEBPDEMUX_DIRS, "webpdemux");
     }
     if cfg!(feature = "mux") {
-        cc::Build::new()
-            .file("c_src/src/mux/anim_encode.c")
-            .file("c_src/src/mux/muxedit.c")
-            .file("c_src/src/mux/muxinternal.c")
-            .file("c_src/src/mux/muxread.c")
-            .include("c_src")
-            .compile("webpmux");
+        compile(LIBWEBPMUX_DIRS, "webpmux");
+    }
+    // libsharpyuv was split out of libwebp in 1.3.0, and libwebp depends on
+    // it from then on.
+    if root.join("sharpyuv").exists() {
+        compile(LIBSHARPYUV_DIRS, "sharpyuv");
+    } else if cfg!(feature = "sharpyuv") {
+        panic!(
+            "the `sharpyuv` feature needs libwebp 1.3.0 or later, but {} ({}) does not \
+             contain libsharpyuv: set LIBWEBP_SYS_SOURCE_DIR to a newer libwebp source tree, \
+             or link a system libwebp",
+            origin,
+            root.display(),
+        );
+    }
+    if threads {
+        link_threads();
+        // Only known for the bundled build; a system libwebp may or may not
+        // have been built with threads.
+        println!("cargo:rustc-cfg=libwebp_threads");
+    }
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    Metadata {
+        include: vec![install_headers(&root, &out_dir)],
+        root: Some(out_dir),
+        version,
+        statik: true,
+    }
+    .emit(origin);
+}
+
+/// Copies the public headers of a libwebp source tree to `$OUT_DIR/include`,
+/// laid out as `make install` does.
+fn install_headers(root: &Path, out_dir: &Path) -> PathBuf {
+    let include = out_dir.join("include");
+    let webp = include.join("webp");
+    fs::create_dir_all(&webp).unwrap();
+    for entry in fs::read_dir(root.join("src/webp")).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension() == Some(OsStr::new("h")) {
+            fs::copy(&path, webp.join(path.file_name().unwrap())).unwrap();
+        }
+    }
+    if root.join("sharpyuv").exists() {
+        let sharpyuv = webp.join("sharpyuv");
+        fs::create_dir_all(&sharpyuv).unwrap();
+        for header in &["sharpyuv.h", "sharpyuv_csp.h"] {
+            fs::copy(root.join("sharpyuv").join(header), sharpyuv.join(header)).unwrap();
+        }
+    }
+    include
+}
+
+/// Whether libwebp's worker threads (pthreads or Win32 threads) are
+/// available on the target.
+fn target_has_threads() -> bool {
+    let family = env::var("CARGO_CFG_TARGET_FAMILY").unwrap_or_default();
+    family.split(',').any(|f| f == "unix" || f == "windows")
+}
+
+fn target_is_wasm_unknown() -> bool {
+    env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default() == "wasm32"
+        && env::var("CARGO_CFG_TARGET_OS").unwrap_or_default() == "unknown"
+}
+
+fn link_threads() {
+    let os = env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+    let family = env::var("CARGO_CFG_TARGET_FAMILY").unwrap_or_default();
+    // Win32 threads live in kernel32, and bionic/libSystem ship pthreads as
+    // part of libc.
+    if family.split(',').any(|f| f == "unix")
+        && !["android", "macos", "ios", "tvos", "watchos"].contains(&os.as_str())
+    {
+        println!("cargo:rustc-link-lib=pthread");
     }
 }
 
 #[cfg(not(target_env = "msvc"))]
-fn try_vcpkg() -> bool {
-    false
+fn try_vcpkg() -> Option<Metadata> {
+    None
 }
 
 #[cfg(target_env = "msvc")]
-fn try_vcpkg() -> bool {
+fn try_vcpkg() -> Option<Metadata> {
     // see if there is a vcpkg tree with libwebp installed
-    match vcpkg::Config::new()
-        .emit_includes(true)
-        .lib_name("libwebp")
-        .probe("libwebp")
-    {
-        Ok(_) => true,
+    let mut config = vcpkg::Config::new();
+    for &(package, _) in &extra_libraries() {
+        config.lib_name(package);
+    }
+    config.lib_name("libwebp");
+    match config.probe("libwebp") {
+        Ok(library) => Some(Metadata {
+            root: library
+                .include_paths
+                .first()
+                .and_then(|include| include.parent())
+                .map(Path::to_path_buf),
+            include: library.include_paths,
+            version: None,
+            statik: library.is_static,
+        }),
         Err(e) => {
             println!("note, vcpkg did not find libwebp: {}", e);
-            false
+            None
+        }
+    }
+}
+
+/// Describes the libwebp found by [`libwebp_installed`]. pkg-config is only
+/// asked for the metadata here, since its link flags are what we avoid on
+/// these platforms; otherwise the headers in the default locations are used.
+fn system_metadata(no_pkg_config: bool) -> Metadata {
+    if !no_pkg_config {
+        if let Ok(library) = pkg_config::Config::new()
+            .cargo_metadata(false)
+            .probe("libwebp")
+        {
+            return Metadata {
+                root: pkg_config::get_variable("libwebp", "prefix")
+                    .ok()
+                    .map(PathBuf::from),
+                include: library.include_paths,
+                version: Some(library.version),
+                statik: false,
+            };
         }
     }
+    let include = ["/usr/local/include", "/usr/include"]
+        .iter()
+        .map(PathBuf::from)
+        .find(|include| include.join("webp/decode.h").exists());
+    Metadata {
+        root: include
+            .as_ref()
+            .and_then(|include| include.parent())
+            .map(Path::to_path_buf),
+        include: include.into_iter().collect(),
+        version: None,
+        statik: false,
+    }
 }
 
 fn libwebp_installed() -> bool {
@@ -0,0 +1,314 @@
+//! Per-macroblock analysis of lossy encodes.
+//!
+//! The VP8 encoder fills `WebPPicture.extra_info` with one byte per 16×16
+//! macroblock, selected by `extra_info_type`. [`Picture::encode_with_map`]
+//! allocates that buffer, returns it as a [`MacroblockMap`], and
+//! [`MacroblockMap::heatmap`] renders it at the size of the picture.
+
+use crate::decoder::{Image, PixelLayout};
+use crate::encode::VP8_ENC_ERROR_INVALID_CONFIGURATION;
+use crate::encoder::{EncodeError, EncoderConfig, Picture};
+
+/// The data recorded per macroblock (`extra_info_type`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MacroblockInfo {
+    /// Intra 4×4 or intra 16×16 prediction.
+    IntraType,
+    /// Segment, 0-3.
+    Segment,
+    /// Quantizer of the segment, 0-127.
+    Quantizer,
+    /// Luma prediction mode of intra 16×16 macroblocks.
+    Intra16Mode,
+    /// Chroma prediction mode.
+    ChromaMode,
+    /// Bytes spent, saturated at 255. Only counted per macroblock with
+    /// `method` 0-2; the token-buffer paths of the higher methods store the
+    /// same value everywhere.
+    BitCost,
+    /// Complexity estimated by the analysis pass.
+    Distortion,
+}
+
+impl MacroblockInfo {
+    fn raw(self) -> i32 {
+        match self {
+            MacroblockInfo::IntraType => 1,
+            MacroblockInfo::Segment => 2,
+            MacroblockInfo::Quantizer => 3,
+            MacroblockInfo::Intra16Mode => 4,
+            MacroblockInfo::ChromaMode => 5,
+            MacroblockInfo::BitCost => 6,
+            MacroblockInfo::Distortion => 7,
+        }
+    }
+
+    fn is_categorical(self) -> bool {
+        match self {
+            MacroblockInfo::IntraType
+            | MacroblockInfo::Segment
+            | MacroblockInfo::Intra16Mode
+            | MacroblockInfo::ChromaMode => true,
+            MacroblockInfo::Quantizer | MacroblockInfo::BitCost | MacroblockInfo::Distortion => {
+                false
+            }
+        }
+    }
+}
+
+/// Prediction mode of a 16×16 luma or 8×8 chroma block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PredictionMode {
+    Dc,
+    TrueMotion,
+    Vertical,
+    Horizontal,
+}
+
+impl PredictionMode {
+    fn from_raw(raw: u8) -> Option<PredictionMode> {
+        match raw {
+            0 => Some(PredictionMode::Dc),
+            1 => Some(PredictionMode::TrueMotion),
+            2 => Some(PredictionMode::Vertical),
+            3 => Some(PredictionMode::Horizontal),
+            _ => None,
+        }
+    }
+}
+
+/// The decoded value of one macroblock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Macroblock {
+    Intra4,
+    Intra16,
+    Segment(u8),
+    Quantizer(u8),
+    /// `None` for intra 4×4 macroblocks.
+    Intra16Mode(Option<PredictionMode>),
+    ChromaMode(Option<PredictionMode>),
+    BitCost(u8),
+    Distortion(u8),
+}
+
+/// One byte per macroblock, in rows of [`MacroblockMap::width`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroblockMap {
+    pub info: MacroblockInfo,
+    /// Width in macroblocks.
+    pub width: u32,
+    /// Height in macroblocks.
+    pub height: u32,
+    /// Width of the picture in pixels.
+    pub picture_width: u32,
+    /// Height of the picture in pixels.
+    pub picture_height: u32,
+    pub data: Vec<u8>,
+}
+
+impl MacroblockMap {
+    /// The raw byte of the macroblock at column `x` and row `y`.
+    pub fn get(&self, x: u32, y: u32) -> Option<u8> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(self.data[(y * self.width + x) as usize])
+    }
+
+    /// The decoded value of the macroblock at column `x` and row `y`.
+    pub fn macroblock(&self, x: u32, y: u32) -> Option<Macroblock> {
+        self.get(x, y).map(|v| self.decode(v))
+    }
+
+    /// The decoded values of all macroblocks, row by row.
+    pub fn macroblocks(&self) -> impl Iterator<Item = Macroblock> + '_ {
+        self.data.iter().map(move |&v| self.decode(v))
+    }
+
+    fn decode(&self, value: u8) -> Macroblock {
+        match self.info {
+            MacroblockInfo::IntraType if value == 0 => Macroblock::Intra4,
+            MacroblockInfo::IntraType => Macroblock::Intra16,
+            MacroblockInfo::Segment => Macroblock::Segment(value),
+            MacroblockInfo::Quantizer => Macroblock::Quantizer(value),
+            MacroblockInfo::Intra16Mode => Macroblock::Intra16Mode(PredictionMode::from_raw(value)),
+            MacroblockInfo::ChromaMode => Macroblock::ChromaMode(PredictionMode::from_raw(value)),
+            MacroblockInfo::BitCost => Macroblock::BitCost(value),
+            MacroblockInfo::Distortion => Macroblock::Distortion(value),
+        }
+    }
+
+    /// Renders the map as an RGB image of the picture size.
+    ///
+    /// Categorical data (intra type, segment, modes) gets one color per
+    /// value; the others a blue to red ramp between the smallest and largest
+    /// value of the map.
+    pub fn heatmap(&self) -> Image {
+        let (min, max) = self
+            .data
+            .iter()
+            .fold((u8::MAX, 0), |(min, max), &v| (min.min(v), max.max(v)));
+        let colors: Vec<[u8; 3]> = self
+            .data
+            .iter()
+            .map(|&v| {
+                if self.info.is_categorical() {
+                    category_color(v)
+                } else {
+                    ramp_color(v, min, max)
+                }
+            })
+            .collect();
+        let (width, height) = (self.picture_width, self.picture_height);
+        let mut data = Vec::with_capacity(width as usize * height as usize * 3);
+        for y in 0..height {
+            for x in 0..width {
+                let index = (y / 16 * self.width + x / 16) as usize;
+                data.extend_from_slice(&colors[index]);
+            }
+        }
+        Image {
+            width,
+            height,
+            layout: PixelLayout::Rgb,
+            data,
+        }
+    }
+}
+
+fn category_color(value: u8) -> [u8; 3] {
+    const PALETTE: [[u8; 3]; 8] = [
+        [0x1f, 0x77, 0xb4],
+        [0xff, 0x7f, 0x0e],
+        [0x2c, 0xa0, 0x2c],
+        [0xd6, 0x27, 0x28],
+        [0x94, 0x67, 0xbd],
+        [0x8c, 0x56, 0x4b],
+        [0xe3, 0x77, 0xc2],
+        [0x7f, 0x7f, 0x7f],
+    ];
+    PALETTE[value as usize % PALETTE.len()]
+}
+
+fn ramp_color(value: u8, min: u8, max: u8) -> [u8; 3] {
+    let t = if max > min {
+        u32::from(value - min) * 255 / u32::from(max - min)
+    } else {
+        0
+    };
+    [t as u8, 0, 255 - t as u8]
+}
+
+impl Picture {
+    /// Encodes the picture lossily and records `info` for every macroblock.
+    ///
+    /// Lossless configurations have no macroblocks and are rejected with
+    /// `VP8_ENC_ERROR_INVALID_CONFIGURATION`.
+    pub fn encode_with_map(
+        &mut self,
+        config: &EncoderConfig,
+        info: MacroblockInfo,
+    ) -> Result<(Vec<u8>, MacroblockMap), EncodeError> {
+        if config.raw().lossless != 0 {
+            return Err(EncodeError::Encoding(VP8_ENC_ERROR_INVALID_CONFIGURATION));
+        }
+        let (picture_width, picture_height) = (self.width(), self.height());
+        let (width, height) = ((picture_width + 15) >> 4, (picture_height + 15) >> 4);
+        let mut data = vec![0u8; width as usize * height as usize];
+        let raw = self.as_mut_ptr();
+        let saved = unsafe { ((*raw).extra_info_type, (*raw).extra_info) };
+        unsafe {
+            (*raw).extra_info_type = info.raw();
+            (*raw).extra_info = data.as_mut_ptr();
+        }
+        let result = self.encode(config);
+        unsafe {
+            (*raw).extra_info_type = saved.0;
+            (*raw).extra_info = saved.1;
+        }
+        let output = result?;
+        Ok((
+            output,
+            MacroblockMap {
+                info,
+                width,
+                height,
+                picture_width,
+                picture_height,
+                data,
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::tests::noise;
+
+    fn picture() -> Picture {
+        // Flat on the left, noisy on the right.
+        let rgb: Vec<u8> = noise(7, 40 * 24 * 3)
+            .into_iter()
+            .enumerate()
+            .map(|(i, n)| if i / 3 % 40 < 16 { 100 } else { n })
+            .collect();
+        Picture::from_rgb(&rgb, 40, 24).unwrap()
+    }
+
+    #[test]
+    fn test_map() {
+        let mut config = EncoderConfig::lossy(50.0).unwrap();
+        config.set_method(2);
+        let (webp, map) = picture()
+            .encode_with_map(&config, MacroblockInfo::BitCost)
+            .unwrap();
+        assert!(!webp.is_empty());
+        assert_eq!((map.width, map.height), (3, 2));
+        assert!(map.get(2, 1).unwrap() > map.get(0, 0).unwrap(), "{:?}", map);
+        assert_eq!(map.get(3, 0), None);
+
+        let (_, types) = picture()
+            .encode_with_map(&config, MacroblockInfo::IntraType)
+            .unwrap();
+        assert!(types
+            .macroblocks()
+            .all(|m| m == Macroblock::Intra4 || m == Macroblock::Intra16));
+
+        let (_, modes) = picture()
+            .encode_with_map(&config, MacroblockInfo::Intra16Mode)
+            .unwrap();
+        for (mode, kind) in modes.macroblocks().zip(types.macroblocks()) {
+            assert_eq!(
+                matches!(mode, Macroblock::Intra16Mode(Some(_))),
+                kind == Macroblock::Intra16
+            );
+        }
+    }
+
+    #[test]
+    fn test_heatmap() {
+        let mut config = EncoderConfig::lossy(50.0).unwrap();
+        config.set_method(2);
+        let (_, map) = picture()
+            .encode_with_map(&config, MacroblockInfo::BitCost)
+            .unwrap();
+        let image = map.heatmap();
+        assert_eq!((image.width, image.height), (40, 24));
+        assert_eq!(image.data.len(), 40 * 24 * 3);
+        let pixel = |x: usize, y: usize| &image.data[(y * 40 + x) * 3..][..3];
+        assert_eq!(pixel(0, 0), pixel(15, 15));
+        assert!(pixel(39, 23)[0] > pixel(0, 0)[0]);
+    }
+
+    #[test]
+    fn test_lossless_rejected() {
+        let config = EncoderConfig::lossless(50.0).unwrap();
+        assert_eq!(
+            picture()
+                .encode_with_map(&config, MacroblockInfo::Segment)
+                .unwrap_err(),
+            EncodeError::Encoding(VP8_ENC_ERROR_INVALID_CONFIGURATION)
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! Safe decoding of animations.
+//!
+//! [`AnimDecoder`] demuxes the input and checks the canvas size, frame count
+//! and duration against [`Limits`] before libwebp allocates the canvas.
+
+use std::marker::PhantomData;
+use std::mem;
+use std::os::raw::*;
+use std::slice;
+use std::time::Duration;
+
+use crate::decode::*;
+use crate::decoder::{DecodeError, Limits, PixelLayout};
+use crate::demux::*;
+use crate::mux_types::*;
+use crate::version;
+
+/// Global information about an animation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimInfo {
+    pub canvas_width: u32,
+    pub canvas_height: u32,
+    /// Number of times to play the animation; 0 means forever.
+    pub loop_count: u32,
+    /// Background color in BGRA byte order (i.e. `0xAARRGGBB` little endian).
+    pub bgcolor: u32,
+    pub frame_count: u32,
+    /// Sum of the durations of all frames.
+    pub duration: Duration,
+}
+
+/// A fully composited frame.
+#[derive(Debug, Clone, Copy)]
+pub struct AnimFrame<'a> {
+    /// The canvas, `canvas_width * 4` bytes per row.
+    pub data: &'a [u8],
+    /// The time at which the frame ends, from the start of the animation.
+    pub timestamp: Duration,
+}
+
+/// Decoder of animated (and still) WebP images.
+pub struct AnimDecoder<'a> {
+    decoder: *mut WebPAnimDecoder,
+    info: AnimInfo,
+    _data: PhantomData<&'a [u8]>,
+}
+
+unsafe impl Send for AnimDecoder<'_> {}
+
+impl<'a> AnimDecoder<'a> {
+    /// Decodes `data` to RGBA with the default [`Limits`].
+    pub fn new(data: &'a [u8]) -> Result<AnimDecoder<'a>, DecodeError> {
+        AnimDecoder::with_limits(data, Limits::default())
+    }
+
+    /// Decodes `data` to RGBA with the given `limits`.
+    pub fn with_limits(data: &'a [u8], limits: Limits) -> Result<AnimDecoder<'a>, DecodeError> {
+        AnimDecoder::with_options(data, PixelLayout::Rgba, false, limits)
+    }
+
+    /// Decodes `data` to `layout`, which must be [`PixelLayout::Rgba`] or
+    /// [`PixelLayout::Bgra`].
+    pub fn with_options(
+        data: &'a [u8],
+        layout: PixelLayout,
+        use_threads: bool,
+        limits: Limits,
+    ) -> Result<AnimDecoder<'a>, DecodeError> {
+        #[cfg(feature = "dynamic")]
+        crate::dynamic::load()?;
+        version::ensure()?;
+        if layout != PixelLayout::Rgba && layout != PixelLayout::Bgra {
+            return Err(DecodeError::Status(VP8_STATUS_INVALID_PARAM));
+        }
+        let webp_data = WebPData {
+            bytes: data.as_ptr(),
+            size: data.len(),
+        };
+        let (width, height, frame_count, duration) = unsafe { demux_info(&webp_data)? };
+        limits.check_pixels(width, height)?;
+        limits.check_frames(frame_count)?;
+        // WebPAnimDecoder keeps the current and the previous canvas, whatever
+        // the number of frames.
+        limits.check_total_bytes(u64::from(width) * u64::from(height) * 4 * 2)?;
+        limits.check_animation_duration(duration)?;
+        unsafe {
+            let mut options: WebPAnimDecoderOptions = mem::zeroed();
+            if WebPAnimDecoderOptionsInit(&mut options) == 0 {
+                return Err(DecodeError::Status(VP8_STATUS_INVALID_PARAM));
+            }
+            options.color_mode = layout.mode();
+            options.use_threads = use_threads as c_int;
+            let decoder = WebPAnimDecoderNew(&webp_data, &options);
+            if decoder.is_null() {
+                return Err(DecodeError::Status(VP8_STATUS_BITSTREAM_ERROR));
+            }
+            let mut info: WebPAnimInfo = mem::zeroed();
+            if WebPAnimDecoderGetInfo(decoder, &mut info) == 0 {
+                WebPAnimDecoderDelete(decoder);
+                return Err(DecodeError::Status(VP8_STATUS_BITSTREAM_ERROR));
+            }
+            Ok(AnimDecoder {
+                decoder,
+                info: AnimInfo {
+                    canvas_width: info.canvas_width,
+                    canvas_height: info.canvas_height,
+                    loop_count: info.loop_count,
+                    bgcolor: info.bgcolor,
+                    frame_count: info.frame_count,
+                    duration,
+                },
+                _data: PhantomData,
+            })
+        }
+    }
+
+    pub fn info(&self) -> AnimInfo {
+        self.info
+    }
+
+    pub fn has_more_frames(&self) -> bool {
+        unsafe { WebPAnimDecoderHasMoreFrames(self.decoder) != 0 }
+    }
+
+    /// Decodes the next frame, or returns `None` after the last one.
+    pub fn next_frame(&mut self) -> Result<Option<AnimFrame<'_>>, DecodeError> {
+        if !self.has_more_frames() {
+            return Ok(None);
+        }
+        let mut buf = std::ptr::null_mut();
+        let mut timestamp = 0;
+        unsafe {
+            if WebPAnimDecoderGetNext(self.decoder, &mut buf, &mut timestamp) == 0 {
+                return Err(DecodeError::Status(VP8_STATUS_BITSTREAM_ERROR));
+            }
+            let size = self.info.canvas_width as usize * self.info.canvas_height as usize * 4;
+            Ok(Some(AnimFrame {
+                data: slice::from_raw_parts(buf, size),
+                timestamp: Duration::from_millis(timestamp.max(0) as u64),
+            }))
+        }
+    }
+
+    /// Rewinds to the first frame.
+    pub fn reset(&mut self) {
+        unsafe { WebPAnimDecoderReset(self.decoder) }
+    }
+}
+
+impl Drop for AnimDecoder<'_> {
+    fn drop(&mut self) {
+        unsafe { WebPAnimDecoderDelete(self.decoder) }
+    }
+}
+
+/// Reads canvas size, frame count and total duration from the container
+/// without decoding any frame.
+unsafe fn demux_info(webp_data: &WebPData) -> Result<(u32, u32, u32, Duration), DecodeError> {
+    let demux = WebPDemux(webp_data);
+    if demux.is_null() {
+        return Err(DecodeError::Status(VP8_STATUS_BITSTREAM_ERROR));
+    }
+    let width = WebPDemuxGetI(demux, WEBP_FF_CANVAS_WIDTH);
+    let height = WebPDemuxGetI(demux, WEBP_FF_CANVAS_HEIGHT);
+    let frame_count = WebPDemuxGetI(demux, WEBP_FF_FRAME_COUNT);
+    let mut duration = 0u64;
+    let mut iter: WebPIterator = mem::zeroed();
+    if WebPDemuxGetFrame(demux, 1, &mut iter) != 0 {
+        loop {
+            duration += iter.duration.max(0) as u64;
+            if WebPDemuxNextFrame(&mut iter) == 0 {
+                break;
+            }
+        }
+        WebPDemuxReleaseIterator(&mut iter);
+    }
+    WebPDemuxDelete(demux);
+    Ok((width, height, frame_count, Duration::from_millis(duration)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::{Limit, LimitExceeded};
+
+    fn animated() -> Vec<u8> {
+        std::fs::read("./tests/animated.webp").unwrap()
+    }
+
+    #[test]
+    fn test_decode_frames() {
+        let data = animated();
+        let mut decoder = AnimDecoder::new(&data).unwrap();
+        let info = decoder.info();
+        assert!(info.frame_count > 1);
+        let mut frames = 0;
+        let mut last = Duration::from_millis(0);
+        while let Some(frame) = decoder.next_frame().unwrap() {
+            assert_eq!(
+                frame.data.len(),
+                (info.canvas_width * info.canvas_height * 4) as usize
+            );
+            last = frame.timestamp;
+            frames += 1;
+        }
+        assert_eq!(frames, info.frame_count);
+        assert_eq!(last, info.duration);
+        decoder.reset();
+        assert!(decoder.has_more_frames());
+    }
+
+    #[test]
+    fn test_limits() {
+        let data = animated();
+        let info = AnimDecoder::new(&data).unwrap().info();
+        let limits = Limits {
+            max_frames: Some(info.frame_count - 1),
+            ..Limits::none()
+        };
+        match AnimDecoder::with_limits(&data, limits) {
+            Err(DecodeError::LimitExceeded(e)) => assert_eq!(e.limit, Limit::Frames),
+            other => panic!("unexpected {:?}", other.map(|d| d.info())),
+        }
+        let limits = Limits {
+            max_animation_duration: Some(info.duration - Duration::from_millis(1)),
+            ..Limits::none()
+        };
+        match AnimDecoder::with_limits(&data, limits) {
+            Err(DecodeError::LimitExceeded(LimitExceeded { limit, .. })) => {
+                assert_eq!(limit, Limit::AnimationDuration)
+            }
+            other => panic!("unexpected {:?}", other.map(|d| d.info())),
+        }
+        let bytes = u64::from(info.canvas_width * info.canvas_height * 4 * 2);
+        let limits = Limits {
+            max_total_bytes: Some(bytes - 1),
+            ..Limits::none()
+        };
+        assert!(AnimDecoder::with_limits(&data, limits).is_err());
+        let limits = Limits {
+            max_total_bytes: Some(bytes),
+            ..Limits::none()
+        };
+        assert!(AnimDecoder::with_limits(&data, limits).is_ok());
+    }
+}
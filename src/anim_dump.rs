@@ -0,0 +1,394 @@
+//! Frame dumps of animations (the `png` feature, with `demux`).
+//!
+//! [`anim_dump`] writes every frame of a WebP to a numbered PNG file, either
+//! the composited canvas rendered by [`AnimDecoder`] or the raw frame
+//! rectangle as stored in the container, together with a [`Manifest`]
+//! recording the timing and placement of each frame.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::time::Duration;
+
+use ::png::{BitDepth, ColorType, Encoder, EncodingError};
+
+use crate::anim::AnimDecoder;
+use crate::decode::VP8_STATUS_BITSTREAM_ERROR;
+use crate::decoder::{DecodeError, Decoder, Limits, PixelLayout};
+use crate::demux::*;
+use crate::mux_types::*;
+
+/// Name of the manifest written next to the frames.
+pub const MANIFEST_FILE: &str = "manifest.tsv";
+
+/// Errors of [`anim_dump`].
+#[derive(Debug)]
+pub enum DumpError {
+    Decode(DecodeError),
+    Png(EncodingError),
+    Io(io::Error),
+}
+
+impl fmt::Display for DumpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DumpError::Decode(e) => e.fmt(f),
+            DumpError::Png(e) => e.fmt(f),
+            DumpError::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for DumpError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DumpError::Decode(e) => Some(e),
+            DumpError::Png(e) => Some(e),
+            DumpError::Io(e) => Some(e),
+        }
+    }
+}
+
+impl From<DecodeError> for DumpError {
+    fn from(e: DecodeError) -> DumpError {
+        DumpError::Decode(e)
+    }
+}
+
+impl From<EncodingError> for DumpError {
+    fn from(e: EncodingError) -> DumpError {
+        DumpError::Png(e)
+    }
+}
+
+impl From<io::Error> for DumpError {
+    fn from(e: io::Error) -> DumpError {
+        DumpError::Io(e)
+    }
+}
+
+/// Which pixels are written for each frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DumpMode {
+    /// The full canvas after the frame is composited
+    /// (`WebPAnimDecoderGetNext`).
+    Composited,
+    /// Only the frame rectangle, before disposal and blending
+    /// (`WebPDemuxGetFrame`).
+    Raw,
+}
+
+/// Disposal of a frame rectangle after its duration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dispose {
+    None,
+    /// Cleared to the background color.
+    Background,
+}
+
+/// How a frame is drawn over the canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Blend {
+    /// Alpha-blended.
+    Blend,
+    /// Replaces the pixels of the rectangle.
+    NoBlend,
+}
+
+/// A frame written by [`anim_dump`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpedFrame {
+    /// The PNG file.
+    pub path: PathBuf,
+    /// Start time of the frame.
+    pub timestamp: Duration,
+    pub duration: Duration,
+    pub x_offset: u32,
+    pub y_offset: u32,
+    /// Width of the frame rectangle.
+    pub width: u32,
+    /// Height of the frame rectangle.
+    pub height: u32,
+    pub has_alpha: bool,
+    pub dispose: Dispose,
+    pub blend: Blend,
+}
+
+/// The frames written by [`anim_dump`] and the global parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub mode: DumpMode,
+    pub canvas_width: u32,
+    pub canvas_height: u32,
+    pub loop_count: u32,
+    /// Background color, as in [`AnimInfo::bgcolor`](crate::anim::AnimInfo).
+    pub bgcolor: u32,
+    pub frames: Vec<DumpedFrame>,
+}
+
+impl Manifest {
+    /// Writes the manifest as tab-separated values: a comment line with the
+    /// global parameters, a header, then one line per frame with times in
+    /// milliseconds.
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mode = match self.mode {
+            DumpMode::Composited => "composited",
+            DumpMode::Raw => "raw",
+        };
+        writeln!(
+            writer,
+            "# mode {} canvas {}x{} loop_count {} bgcolor 0x{:08x}",
+            mode, self.canvas_width, self.canvas_height, self.loop_count, self.bgcolor
+        )?;
+        writeln!(
+            writer,
+            "index\tfile\ttimestamp\tduration\tx_offset\ty_offset\twidth\theight\thas_alpha\tdispose\tblend"
+        )?;
+        for (index, frame) in self.frames.iter().enumerate() {
+            let file = frame.path.file_name().unwrap_or_default();
+            let dispose = match frame.dispose {
+                Dispose::None => "none",
+                Dispose::Background => "background",
+            };
+            let blend = match frame.blend {
+                Blend::Blend => "blend",
+                Blend::NoBlend => "no_blend",
+            };
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                index,
+                file.to_string_lossy(),
+                frame.timestamp.as_millis(),
+                frame.duration.as_millis(),
+                frame.x_offset,
+                frame.y_offset,
+                frame.width,
+                frame.height,
+                frame.has_alpha,
+                dispose,
+                blend
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes every frame of `data` to `dir` as `frame_0000.png`,
+/// `frame_0001.png`, …, and the manifest as [`MANIFEST_FILE`].
+///
+/// `dir` is created if needed. The canvas, frame count and frame sizes are
+/// checked against the default [`Limits`].
+pub fn anim_dump<P: AsRef<Path>>(
+    data: &[u8],
+    dir: P,
+    mode: DumpMode,
+) -> Result<Manifest, DumpError> {
+    let dir = dir.as_ref();
+    let mut manifest = demux(data)?;
+    manifest.mode = mode;
+    fs::create_dir_all(dir)?;
+    for (index, frame) in manifest.frames.iter_mut().enumerate() {
+        frame.path = dir.join(format!("frame_{:04}.png", index));
+    }
+
+    match mode {
+        DumpMode::Composited => {
+            let mut decoder = AnimDecoder::new(data)?;
+            let (width, height) = (manifest.canvas_width, manifest.canvas_height);
+            for frame in &manifest.frames {
+                let canvas = decoder
+                    .next_frame()?
+                    .ok_or(DecodeError::Status(VP8_STATUS_BITSTREAM_ERROR))?;
+                write_png(&frame.path, width, height, canvas.data)?;
+            }
+        }
+        DumpMode::Raw => {
+            for (frame, fragment) in manifest.frames.iter().zip(fragments(data)?) {
+                let image = Decoder::new(fragment)?.decode(PixelLayout::Rgba)?;
+                write_png(&frame.path, image.width, image.height, &image.data)?;
+            }
+        }
+    }
+
+    let mut writer = BufWriter::new(File::create(dir.join(MANIFEST_FILE))?);
+    manifest.write(&mut writer)?;
+    writer.flush()?;
+    Ok(manifest)
+}
+
+fn write_png(path: &Path, width: u32, height: u32, rgba: &[u8]) -> Result<(), DumpError> {
+    let mut encoder = Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(ColorType::Rgba);
+    encoder.set_depth(BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgba)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Reads the global parameters and the frame list, with empty paths.
+fn demux(data: &[u8]) -> Result<Manifest, DecodeError> {
+    let limits = Limits::default();
+    let mut manifest = None;
+    with_frames(data, |demux, iter| {
+        let manifest = manifest.get_or_insert_with(|| unsafe {
+            Manifest {
+                mode: DumpMode::Composited,
+                canvas_width: WebPDemuxGetI(demux, WEBP_FF_CANVAS_WIDTH),
+                canvas_height: WebPDemuxGetI(demux, WEBP_FF_CANVAS_HEIGHT),
+                loop_count: WebPDemuxGetI(demux, WEBP_FF_LOOP_COUNT),
+                bgcolor: WebPDemuxGetI(demux, WEBP_FF_BACKGROUND_COLOR),
+                frames: Vec::new(),
+            }
+        });
+        let timestamp = manifest
+            .frames
+            .last()
+            .map_or(Duration::from_millis(0), |f| f.timestamp + f.duration);
+        manifest.frames.push(DumpedFrame {
+            path: PathBuf::new(),
+            timestamp,
+            duration: Duration::from_millis(iter.duration.max(0) as u64),
+            x_offset: iter.x_offset as u32,
+            y_offset: iter.y_offset as u32,
+            width: iter.width as u32,
+            height: iter.height as u32,
+            has_alpha: iter.has_alpha != 0,
+            dispose: match iter.dispose_method {
+                WEBP_MUX_DISPOSE_BACKGROUND => Dispose::Background,
+                _ => Dispose::None,
+            },
+            blend: match iter.blend_method {
+                WEBP_MUX_NO_BLEND => Blend::NoBlend,
+                _ => Blend::Blend,
+            },
+        });
+    })?;
+    let manifest = manifest.ok_or(DecodeError::Status(VP8_STATUS_BITSTREAM_ERROR))?;
+    limits.check_pixels(manifest.canvas_width, manifest.canvas_height)?;
+    limits.check_frames(manifest.frames.len() as u32)?;
+    Ok(manifest)
+}
+
+/// The bitstream of every frame.
+fn fragments(data: &[u8]) -> Result<Vec<&[u8]>, DecodeError> {
+    let mut fragments = Vec::new();
+    with_frames(data, |_, iter| {
+        // The fragment points into `data`, not into the demuxer.
+        fragments.push(unsafe { slice::from_raw_parts(iter.fragment.bytes, iter.fragment.size) });
+    })?;
+    Ok(fragments)
+}
+
+/// Calls `f` with every frame of a complete WebP.
+fn with_frames(
+    data: &[u8],
+    mut f: impl FnMut(*const WebPDemuxer, &WebPIterator),
+) -> Result<(), DecodeError> {
+    let webp_data = WebPData {
+        bytes: data.as_ptr(),
+        size: data.len(),
+    };
+    unsafe {
+        let demux = WebPDemux(&webp_data);
+        if demux.is_null() {
+            return Err(DecodeError::Status(VP8_STATUS_BITSTREAM_ERROR));
+        }
+        let mut iter: WebPIterator = mem::zeroed();
+        if WebPDemuxGetFrame(demux, 1, &mut iter) != 0 {
+            loop {
+                f(demux, &iter);
+                if WebPDemuxNextFrame(&mut iter) == 0 {
+                    break;
+                }
+            }
+            WebPDemuxReleaseIterator(&mut iter);
+        }
+        WebPDemuxDelete(demux);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animated() -> Vec<u8> {
+        std::fs::read("./tests/animated.webp").unwrap()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "libwebp-sys-anim-dump-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn read_png(path: &Path) -> (u32, u32, Vec<u8>) {
+        let decoder = ::png::Decoder::new(File::open(path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).unwrap();
+        (info.width, info.height, buffer)
+    }
+
+    #[test]
+    fn test_composited() {
+        let data = animated();
+        let dir = temp_dir("composited");
+        let manifest = anim_dump(&data, &dir, DumpMode::Composited).unwrap();
+
+        let mut decoder = AnimDecoder::new(&data).unwrap();
+        let info = decoder.info();
+        assert_eq!(manifest.frames.len() as u32, info.frame_count);
+        assert_eq!(manifest.loop_count, info.loop_count);
+        let last = manifest.frames.last().unwrap();
+        assert_eq!(last.timestamp + last.duration, info.duration);
+        for frame in &manifest.frames {
+            let canvas = decoder.next_frame().unwrap().unwrap();
+            assert_eq!(canvas.timestamp, frame.timestamp + frame.duration);
+            let (width, height, pixels) = read_png(&frame.path);
+            assert_eq!((width, height), (info.canvas_width, info.canvas_height));
+            assert!(pixels == canvas.data);
+        }
+
+        let text = fs::read_to_string(dir.join(MANIFEST_FILE)).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert!(lines[0].starts_with("# mode composited"));
+        assert_eq!(lines.len(), manifest.frames.len() + 2);
+        assert!(lines[2].starts_with("0\tframe_0000.png\t0\t"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_raw() {
+        let data = animated();
+        let dir = temp_dir("raw");
+        let manifest = anim_dump(&data, &dir, DumpMode::Raw).unwrap();
+        for frame in &manifest.frames {
+            let (width, height, _) = read_png(&frame.path);
+            assert_eq!((width, height), (frame.width, frame.height));
+            assert!(frame.x_offset + frame.width <= manifest.canvas_width);
+            assert!(frame.y_offset + frame.height <= manifest.canvas_height);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid() {
+        let dir = temp_dir("invalid");
+        assert!(matches!(
+            anim_dump(b"RIFF", &dir, DumpMode::Raw),
+            Err(DumpError::Decode(_))
+        ));
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,305 @@
+//! Safe encoding of animations.
+//!
+//! [`AnimEncoder`] wraps `WebPAnimEncoder`: frames are added as full canvas
+//! [`Picture`]s with their start time, and libwebp picks the sub-rectangles,
+//! disposal and blending of each frame.
+
+use std::error::Error;
+use std::ffi::CStr;
+use std::fmt;
+use std::mem;
+use std::os::raw::*;
+use std::ptr;
+use std::slice;
+use std::time::Duration;
+
+use crate::encode::VP8_ENC_ERROR_BAD_DIMENSION;
+use crate::encoder::{EncodeError, EncoderConfig, Picture};
+use crate::mux::*;
+use crate::mux_types::*;
+use crate::version;
+
+/// Errors of [`AnimEncoder`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnimEncodeError {
+    Encode(EncodeError),
+    /// `WebPAnimEncoder` failed, with its error message.
+    Failed(String),
+}
+
+impl fmt::Display for AnimEncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnimEncodeError::Encode(e) => e.fmt(f),
+            AnimEncodeError::Failed(message) => f.write_str(message),
+        }
+    }
+}
+
+impl Error for AnimEncodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AnimEncodeError::Encode(e) => Some(e),
+            AnimEncodeError::Failed(_) => None,
+        }
+    }
+}
+
+impl From<EncodeError> for AnimEncodeError {
+    fn from(e: EncodeError) -> AnimEncodeError {
+        AnimEncodeError::Encode(e)
+    }
+}
+
+impl From<version::Report> for AnimEncodeError {
+    fn from(report: version::Report) -> AnimEncodeError {
+        AnimEncodeError::Encode(EncodeError::Incompatible(report))
+    }
+}
+
+#[cfg(feature = "dynamic")]
+impl From<crate::dynamic::Error> for AnimEncodeError {
+    fn from(e: crate::dynamic::Error) -> AnimEncodeError {
+        AnimEncodeError::Encode(EncodeError::Load(e))
+    }
+}
+
+/// Global parameters of an animation.
+#[derive(Clone, Copy)]
+pub struct AnimEncoderOptions {
+    raw: WebPAnimEncoderOptions,
+}
+
+impl fmt::Debug for AnimEncoderOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AnimEncoderOptions")
+            .field("loop_count", &self.raw.anim_params.loop_count)
+            .field("bgcolor", &self.raw.anim_params.bgcolor)
+            .field("minimize_size", &self.raw.minimize_size)
+            .field("kmin", &self.raw.kmin)
+            .field("kmax", &self.raw.kmax)
+            .field("allow_mixed", &self.raw.allow_mixed)
+            .finish()
+    }
+}
+
+impl AnimEncoderOptions {
+    /// The libwebp defaults: loop forever, keyframes chosen automatically.
+    pub fn new() -> Result<AnimEncoderOptions, AnimEncodeError> {
+        #[cfg(feature = "dynamic")]
+        crate::dynamic::load()?;
+        version::ensure()?;
+        let mut raw: WebPAnimEncoderOptions = unsafe { mem::zeroed() };
+        if unsafe { WebPAnimEncoderOptionsInit(&mut raw) } == 0 {
+            return Err(AnimEncodeError::Failed(
+                "incompatible WebPAnimEncoderOptions".to_string(),
+            ));
+        }
+        Ok(AnimEncoderOptions { raw })
+    }
+
+    /// Number of times to play the animation; 0 means forever.
+    pub fn set_loop_count(&mut self, loop_count: u32) -> &mut Self {
+        self.raw.anim_params.loop_count = loop_count as c_int;
+        self
+    }
+
+    /// Background color, as in [`AnimInfo::bgcolor`](crate::anim::AnimInfo).
+    pub fn set_bgcolor(&mut self, bgcolor: u32) -> &mut Self {
+        self.raw.anim_params.bgcolor = bgcolor;
+        self
+    }
+
+    /// Tries harder to find the smallest frame rectangles and methods.
+    pub fn set_minimize_size(&mut self, minimize_size: bool) -> &mut Self {
+        self.raw.minimize_size = minimize_size as c_int;
+        self
+    }
+
+    /// A keyframe is inserted at most every `kmax` and at least every `kmin`
+    /// frames.
+    pub fn set_keyframe_interval(&mut self, kmin: c_int, kmax: c_int) -> &mut Self {
+        self.raw.kmin = kmin;
+        self.raw.kmax = kmax;
+        self
+    }
+
+    /// Lets libwebp choose between lossy and lossless for each frame.
+    pub fn set_allow_mixed(&mut self, allow_mixed: bool) -> &mut Self {
+        self.raw.allow_mixed = allow_mixed as c_int;
+        self
+    }
+
+    pub fn raw(&self) -> &WebPAnimEncoderOptions {
+        &self.raw
+    }
+
+    /// The underlying `WebPAnimEncoderOptions`, for parameters without a
+    /// setter.
+    pub fn raw_mut(&mut self) -> &mut WebPAnimEncoderOptions {
+        &mut self.raw
+    }
+}
+
+/// Encoder of animations.
+pub struct AnimEncoder {
+    encoder: *mut WebPAnimEncoder,
+    width: u32,
+    height: u32,
+}
+
+unsafe impl Send for AnimEncoder {}
+
+impl fmt::Debug for AnimEncoder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AnimEncoder")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
+
+impl AnimEncoder {
+    /// An encoder for a `width` × `height` canvas.
+    pub fn new(
+        width: u32,
+        height: u32,
+        options: &AnimEncoderOptions,
+    ) -> Result<AnimEncoder, AnimEncodeError> {
+        #[cfg(feature = "dynamic")]
+        crate::dynamic::load()?;
+        version::ensure()?;
+        if width > c_int::MAX as u32 || height > c_int::MAX as u32 {
+            return Err(AnimEncodeError::Encode(EncodeError::Encoding(
+                VP8_ENC_ERROR_BAD_DIMENSION,
+            )));
+        }
+        let encoder = unsafe { WebPAnimEncoderNew(width as c_int, height as c_int, &options.raw) };
+        if encoder.is_null() {
+            return Err(AnimEncodeError::Failed(
+                "could not create WebPAnimEncoder".to_string(),
+            ));
+        }
+        Ok(AnimEncoder {
+            encoder,
+            width,
+            height,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Adds a frame shown from `timestamp` on. The picture must have the
+    /// size of the canvas and timestamps must increase.
+    pub fn add_frame(
+        &mut self,
+        picture: &mut Picture,
+        timestamp: Duration,
+        config: &EncoderConfig,
+    ) -> Result<(), AnimEncodeError> {
+        config.validate()?;
+        let ok = unsafe {
+            WebPAnimEncoderAdd(
+                self.encoder,
+                picture.as_mut_ptr(),
+                timestamp_ms(timestamp)?,
+                config.raw(),
+            )
+        };
+        if ok == 0 {
+            return Err(self.error());
+        }
+        Ok(())
+    }
+
+    /// Ends the last frame at `end` and assembles the animation.
+    pub fn finish(self, end: Duration) -> Result<Vec<u8>, AnimEncodeError> {
+        unsafe {
+            let timestamp = timestamp_ms(end)?;
+            if WebPAnimEncoderAdd(self.encoder, ptr::null_mut(), timestamp, ptr::null()) == 0 {
+                return Err(self.error());
+            }
+            let mut data: WebPData = mem::zeroed();
+            if WebPAnimEncoderAssemble(self.encoder, &mut data) == 0 {
+                return Err(self.error());
+            }
+            let output = slice::from_raw_parts(data.bytes, data.size).to_vec();
+            WebPDataClear(&mut data);
+            Ok(output)
+        }
+    }
+
+    fn error(&self) -> AnimEncodeError {
+        let message = unsafe { WebPAnimEncoderGetError(self.encoder) };
+        if message.is_null() {
+            return AnimEncodeError::Failed("WebPAnimEncoder failed".to_string());
+        }
+        let message = unsafe { CStr::from_ptr(message) };
+        AnimEncodeError::Failed(message.to_string_lossy().into_owned())
+    }
+}
+
+impl Drop for AnimEncoder {
+    fn drop(&mut self) {
+        unsafe { WebPAnimEncoderDelete(self.encoder) }
+    }
+}
+
+fn timestamp_ms(timestamp: Duration) -> Result<c_int, AnimEncodeError> {
+    let ms = timestamp.as_millis();
+    if ms > c_int::MAX as u128 {
+        return Err(AnimEncodeError::Failed(
+            "timestamp out of range".to_string(),
+        ));
+    }
+    Ok(ms as c_int)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(value: u8) -> Picture {
+        Picture::from_rgba(&[value; 16 * 16 * 4], 16, 16).unwrap()
+    }
+
+    #[test]
+    fn test_encode() {
+        let mut options = AnimEncoderOptions::new().unwrap();
+        options.set_loop_count(3).set_bgcolor(0xff00_00ff);
+        let mut encoder = AnimEncoder::new(16, 16, &options).unwrap();
+        let config = EncoderConfig::lossless(50.0).unwrap();
+        for (i, value) in [40, 120, 200].iter().enumerate() {
+            encoder
+                .add_frame(
+                    &mut frame(*value),
+                    Duration::from_millis(100 * i as u64),
+                    &config,
+                )
+                .unwrap();
+        }
+        let webp = encoder.finish(Duration::from_millis(350)).unwrap();
+
+        let features = crate::decoder::get_features(&webp).unwrap();
+        assert!(features.has_animation);
+        assert_eq!((features.width, features.height), (16, 16));
+    }
+
+    #[test]
+    fn test_errors() {
+        let options = AnimEncoderOptions::new().unwrap();
+        let mut encoder = AnimEncoder::new(16, 16, &options).unwrap();
+        let config = EncoderConfig::new().unwrap();
+        let mut small = Picture::from_rgba(&[0; 8 * 8 * 4], 8, 8).unwrap();
+        match encoder.add_frame(&mut small, Duration::from_millis(0), &config) {
+            Err(AnimEncodeError::Failed(message)) => assert!(!message.is_empty()),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
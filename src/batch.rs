@@ -0,0 +1,344 @@
+//! Parallel encoding of many images (the `rayon` feature).
+//!
+//! [`BatchEncoder`] encodes a slice of [`Input`]s on a rayon thread pool with
+//! one shared [`EncoderConfig`]. Each worker keeps its `WebPPicture` between
+//! items, refilling the ARGB plane in place when the dimensions match, and
+//! sizes each output buffer after the previous one. Parallelism comes from
+//! encoding several images at once, so `thread_level` is turned off inside
+//! each encode.
+
+use std::mem;
+use std::os::raw::c_int;
+use std::slice;
+use std::sync::{Condvar, Mutex};
+
+use rayon::prelude::*;
+use rayon::ThreadPool;
+
+use crate::decoder::PixelLayout;
+use crate::encode::WebPAuxStats;
+use crate::encoder::{EncodeError, EncoderConfig, Picture};
+use crate::stats::EncodeStats;
+
+/// Rough upper bound on the memory libwebp needs per pixel while encoding
+/// (ARGB, YUVA and the lossless working buffers).
+const BYTES_PER_PIXEL_ESTIMATE: u64 = 24;
+
+/// Packed pixels to encode.
+#[derive(Debug, Clone, Copy)]
+pub struct Input<'a> {
+    pub layout: PixelLayout,
+    pub data: &'a [u8],
+    pub width: u32,
+    pub height: u32,
+    /// Distance between rows in bytes.
+    pub stride: usize,
+}
+
+impl<'a> Input<'a> {
+    /// Tightly packed pixels.
+    pub fn new(layout: PixelLayout, data: &'a [u8], width: u32, height: u32) -> Input<'a> {
+        Input {
+            layout,
+            data,
+            width,
+            height,
+            stride: width as usize * layout.bytes_per_pixel(),
+        }
+    }
+
+    fn memory_estimate(&self) -> u64 {
+        u64::from(self.width) * u64::from(self.height) * BYTES_PER_PIXEL_ESTIMATE
+    }
+}
+
+/// An encoded image.
+#[derive(Debug, Clone)]
+pub struct BatchOutput {
+    pub data: Vec<u8>,
+    pub stats: WebPAuxStats,
+}
+
+impl BatchOutput {
+    /// The statistics as an [`EncodeStats`].
+    pub fn encode_stats(&self) -> EncodeStats {
+        EncodeStats::from(&self.stats)
+    }
+}
+
+/// Encoder of many images in parallel.
+pub struct BatchEncoder {
+    config: EncoderConfig,
+    max_memory: Option<u64>,
+    pool: Option<ThreadPool>,
+}
+
+impl BatchEncoder {
+    pub fn new(config: &EncoderConfig) -> BatchEncoder {
+        let mut config = *config;
+        config.raw_mut().thread_level = 0;
+        BatchEncoder {
+            config,
+            max_memory: None,
+            pool: None,
+        }
+    }
+
+    /// Limits the estimated memory of the encodes running at the same time,
+    /// including the pictures the workers keep for reuse. An image exceeding
+    /// the limit on its own is encoded alone.
+    pub fn max_memory(&mut self, bytes: u64) -> &mut Self {
+        self.max_memory = Some(bytes);
+        self
+    }
+
+    /// Uses a dedicated pool of `threads` threads instead of the global rayon
+    /// pool.
+    pub fn num_threads(
+        &mut self,
+        threads: usize,
+    ) -> Result<&mut Self, rayon::ThreadPoolBuildError> {
+        self.pool = Some(
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()?,
+        );
+        Ok(self)
+    }
+
+    /// Encodes all inputs. The results are in the order of `inputs`.
+    pub fn encode(&self, inputs: &[Input<'_>]) -> Vec<Result<BatchOutput, EncodeError>> {
+        let budget = self.max_memory.map(Budget::new);
+        let run = || {
+            inputs
+                .par_iter()
+                .map_init(Worker::default, |worker, input| {
+                    worker.encode(&self.config, input, budget.as_ref())
+                })
+                .collect()
+        };
+        match &self.pool {
+            Some(pool) => pool.install(run),
+            None => run(),
+        }
+    }
+}
+
+/// State reused by the items processed on one worker.
+#[derive(Default)]
+struct Worker<'b> {
+    picture: Option<Picture>,
+    /// The share of the budget of `picture`, held for as long as it is kept.
+    permit: Option<Permit<'b>>,
+    /// Size of the previous output, to allocate the next one at once.
+    output_hint: usize,
+}
+
+impl<'b> Worker<'b> {
+    fn encode(
+        &mut self,
+        config: &EncoderConfig,
+        input: &Input<'_>,
+        budget: Option<&'b Budget>,
+    ) -> Result<BatchOutput, EncodeError> {
+        let reused = match &mut self.picture {
+            Some(picture) => refill(picture, input)?,
+            None => false,
+        };
+        if !reused {
+            // Free the previous picture and its share first to keep the peak
+            // memory low.
+            self.picture = None;
+            self.permit = None;
+            self.permit = budget.map(|budget| budget.acquire(input.memory_estimate()));
+            match Picture::import(
+                input.layout,
+                input.data,
+                input.width,
+                input.height,
+                input.stride,
+            ) {
+                Ok(picture) => self.picture = Some(picture),
+                Err(e) => {
+                    self.permit = None;
+                    return Err(e);
+                }
+            }
+        }
+        let picture = self.picture.as_mut().unwrap();
+        let mut stats: WebPAuxStats = unsafe { mem::zeroed() };
+        let mut output = Vec::with_capacity(self.output_hint);
+        picture.encode_raw(config, &mut output, &mut stats)?;
+        self.output_hint = output.len();
+        Ok(BatchOutput {
+            data: output,
+            stats,
+        })
+    }
+}
+
+/// Converts the input into the ARGB plane of `picture` if it has one of the
+/// same size. Returns whether it did.
+fn refill(picture: &mut Picture, input: &Input<'_>) -> Result<bool, EncodeError> {
+    let raw = unsafe { &mut *picture.as_mut_ptr() };
+    if raw.argb.is_null()
+        || raw.width as u32 != input.width
+        || raw.height as u32 != input.height
+        || input.layout == PixelLayout::Argb
+        || input.stride > c_int::MAX as usize
+    {
+        // Picture::import reports unsupported layouts and strides.
+        return Ok(false);
+    }
+    let (width, height) = (input.width as usize, input.height as usize);
+    let bpp = input.layout.bytes_per_pixel();
+    if height > 0 {
+        let fits = input
+            .stride
+            .checked_mul(height - 1)
+            .and_then(|size| size.checked_add(width * bpp))
+            .map(|needed| input.data.len() >= needed);
+        if input.stride < width * bpp || fits != Some(true) {
+            return Err(EncodeError::BufferTooSmall);
+        }
+    }
+    for y in 0..height {
+        let src = &input.data[y * input.stride..][..width * bpp];
+        let dst =
+            unsafe { slice::from_raw_parts_mut(raw.argb.add(y * raw.argb_stride as usize), width) };
+        for (dst, p) in dst.iter_mut().zip(src.chunks_exact(bpp)) {
+            let [a, r, g, b] = match input.layout {
+                PixelLayout::Rgb => [0xff, p[0], p[1], p[2]],
+                PixelLayout::Rgba => [p[3], p[0], p[1], p[2]],
+                PixelLayout::Bgr => [0xff, p[2], p[1], p[0]],
+                PixelLayout::Bgra => [p[3], p[2], p[1], p[0]],
+                PixelLayout::Argb => unreachable!(),
+            };
+            *dst = u32::from_be_bytes([a, r, g, b]);
+        }
+    }
+    raw.use_argb = 1;
+    Ok(true)
+}
+
+/// Counting semaphore over estimated bytes.
+struct Budget {
+    max: u64,
+    used: Mutex<u64>,
+    released: Condvar,
+}
+
+struct Permit<'a> {
+    budget: &'a Budget,
+    bytes: u64,
+}
+
+impl Budget {
+    fn new(max: u64) -> Budget {
+        Budget {
+            max,
+            used: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    fn acquire(&self, bytes: u64) -> Permit<'_> {
+        let bytes = bytes.min(self.max);
+        let mut used = self.used.lock().unwrap();
+        while *used + bytes > self.max {
+            used = self.released.wait(used).unwrap();
+        }
+        *used += bytes;
+        Permit {
+            budget: self,
+            bytes,
+        }
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self.budget.used.lock().unwrap() -= self.bytes;
+        self.budget.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+
+    fn image(seed: u8, width: u32, height: u32) -> Vec<u8> {
+        (0..width * height * 4)
+            .map(|i| (i as u8).wrapping_mul(seed))
+            .collect()
+    }
+
+    #[test]
+    fn test_batch() {
+        let images: Vec<_> = (1..=12)
+            .map(|i| (image(i, 8 + u32::from(i % 3), 8), 8 + u32::from(i % 3)))
+            .collect();
+        let mut inputs: Vec<_> = images
+            .iter()
+            .map(|(data, width)| Input::new(PixelLayout::Rgba, data, *width, 8))
+            .collect();
+        inputs.push(Input::new(PixelLayout::Rgba, &[0; 4], 2, 2));
+
+        let mut encoder = BatchEncoder::new(&EncoderConfig::lossless(10.0).unwrap());
+        encoder.max_memory(8 * 8 * 48).num_threads(3).unwrap();
+        let outputs = encoder.encode(&inputs);
+        assert_eq!(outputs.len(), 13);
+        for ((data, width), output) in images.iter().zip(&outputs) {
+            let output = output.as_ref().unwrap();
+            assert_eq!(output.stats.coded_size as usize, output.data.len());
+            assert_eq!(output.encode_stats().coded_size, output.data.len());
+            let decoded = Decoder::new(&output.data)
+                .unwrap()
+                .decode(PixelLayout::Rgba)
+                .unwrap();
+            assert_eq!(decoded.width, *width);
+            assert_eq!(&decoded.data, data);
+        }
+        assert_eq!(
+            outputs[12].as_ref().unwrap_err(),
+            &EncodeError::BufferTooSmall
+        );
+    }
+
+    #[test]
+    fn test_retained_picture_in_budget() {
+        let budget = Budget::new(1 << 20);
+        let config = EncoderConfig::lossless(10.0).unwrap();
+        let (small, large) = (image(3, 4, 4), image(5, 8, 8));
+        let mut worker = Worker::default();
+        worker
+            .encode(
+                &config,
+                &Input::new(PixelLayout::Rgba, &large, 8, 8),
+                Some(&budget),
+            )
+            .unwrap();
+        assert_eq!(
+            *budget.used.lock().unwrap(),
+            8 * 8 * BYTES_PER_PIXEL_ESTIMATE
+        );
+        let input = Input::new(PixelLayout::Rgba, &small, 4, 4);
+        let output = worker.encode(&config, &input, Some(&budget)).unwrap();
+        assert_eq!(*budget.used.lock().unwrap(), input.memory_estimate());
+        assert_eq!(worker.output_hint, output.data.len());
+        drop(worker);
+        assert_eq!(*budget.used.lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_refill_matches_import() {
+        let data = image(7, 4, 3);
+        let input = Input::new(PixelLayout::Bgra, &data, 4, 3);
+        let imported = Picture::import(PixelLayout::Bgra, &data, 4, 3, 16).unwrap();
+        let mut picture = Picture::new(4, 3).unwrap();
+        assert!(refill(&mut picture, &input).unwrap());
+        let argb = |p: &Picture| unsafe { slice::from_raw_parts(p.as_raw().argb, 12).to_vec() };
+        assert_eq!(argb(&picture), argb(&imported));
+    }
+}
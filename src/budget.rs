@@ -0,0 +1,190 @@
+//! Encoding to a byte budget.
+//!
+//! `WebPConfig.target_size` steers the encoder over a few partial passes and
+//! ignores the alpha plane and the container. [`encode_to_budget`] instead
+//! searches `quality` over complete encodes, so the size limit holds for the
+//! actual output file.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::encoder::{EncodeError, EncoderConfig, Picture};
+use crate::metrics::{self, CompareError, Metric};
+
+/// Stop searching once the quality interval is narrower than this.
+const QUALITY_TOLERANCE: f32 = 0.5;
+
+/// Result of [`encode_to_budget`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetResult {
+    /// The encoded image, at most `max_bytes` long.
+    pub data: Vec<u8>,
+    /// The highest quality found to fit.
+    pub quality: f32,
+    /// Number of complete encodes performed.
+    pub iterations: u32,
+    /// PSNR in dB of the decoded result against the picture, all channels.
+    pub psnr: f32,
+    /// SSIM in dB (`-10 log10(1 - ssim)`) of the decoded result against the
+    /// picture, all channels.
+    pub ssim: f32,
+}
+
+/// Errors of [`encode_to_budget`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BudgetError {
+    Encode(EncodeError),
+    /// The result could not be decoded or measured.
+    Measure(CompareError),
+    /// Even quality 0 does not fit; holds the size at quality 0.
+    OverBudget {
+        smallest: usize,
+    },
+}
+
+impl fmt::Display for BudgetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BudgetError::Encode(e) => e.fmt(f),
+            BudgetError::Measure(e) => e.fmt(f),
+            BudgetError::OverBudget { smallest } => {
+                write!(
+                    f,
+                    "the smallest encoding is {} bytes, over budget",
+                    smallest
+                )
+            }
+        }
+    }
+}
+
+impl Error for BudgetError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BudgetError::Encode(e) => Some(e),
+            BudgetError::Measure(e) => Some(e),
+            BudgetError::OverBudget { .. } => None,
+        }
+    }
+}
+
+impl From<EncodeError> for BudgetError {
+    fn from(e: EncodeError) -> BudgetError {
+        BudgetError::Encode(e)
+    }
+}
+
+/// Encodes `picture` lossily at the highest quality whose output is at most
+/// `max_bytes` long.
+///
+/// All other parameters come from `base_config`; its `lossless`,
+/// `target_size` and `target_PSNR` are ignored. `picture` itself is not
+/// modified.
+pub fn encode_to_budget(
+    picture: &Picture,
+    max_bytes: usize,
+    base_config: &EncoderConfig,
+) -> Result<BudgetResult, BudgetError> {
+    let mut config = *base_config;
+    {
+        let raw = config.raw_mut();
+        raw.lossless = 0;
+        raw.target_size = 0;
+        raw.target_PSNR = 0.0;
+    }
+    // The copy is converted to YUV by the first encode and reused after.
+    let mut work = picture.try_clone()?;
+    let mut iterations = 0;
+    let mut encode = |quality: f32| -> Result<Vec<u8>, EncodeError> {
+        iterations += 1;
+        config.set_quality(quality);
+        work.encode(&config)
+    };
+
+    let mut best = None;
+    let data = encode(100.0)?;
+    if data.len() <= max_bytes {
+        best = Some((100.0, data));
+    } else {
+        let (mut low, mut high) = (0.0f32, 100.0f32);
+        while high - low > QUALITY_TOLERANCE {
+            let quality = (low + high) / 2.0;
+            let data = encode(quality)?;
+            if data.len() <= max_bytes {
+                best = Some((quality, data));
+                low = quality;
+            } else {
+                high = quality;
+            }
+        }
+        if best.is_none() {
+            let data = encode(0.0)?;
+            if data.len() > max_bytes {
+                return Err(BudgetError::OverBudget {
+                    smallest: data.len(),
+                });
+            }
+            best = Some((0.0, data));
+        }
+    }
+    let (quality, data) = best.unwrap();
+    let (psnr, ssim) = measure(picture, &data).map_err(BudgetError::Measure)?;
+    Ok(BudgetResult {
+        data,
+        quality,
+        iterations,
+        psnr,
+        ssim,
+    })
+}
+
+/// Decodes `data` and returns its overall PSNR and SSIM against `picture`.
+fn measure(picture: &Picture, data: &[u8]) -> Result<(f32, f32), CompareError> {
+    let decoded = metrics::decode_picture(data)?;
+    let psnr = metrics::compare(picture, &decoded, Metric::Psnr)?;
+    let ssim = metrics::compare(picture, &decoded, Metric::Ssim)?;
+    Ok((psnr.all, ssim.all))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::tests::noise;
+
+    fn noisy_picture() -> Picture {
+        let rgb: Vec<u8> = noise(1, 64 * 64 * 3)
+            .into_iter()
+            .enumerate()
+            .map(|(i, n)| (n / 4).wrapping_add((i / 192) as u8 * 2))
+            .collect();
+        Picture::from_rgb(&rgb, 64, 64).unwrap()
+    }
+
+    #[test]
+    fn test_encode_to_budget() {
+        let picture = noisy_picture();
+        let config = EncoderConfig::new().unwrap();
+        let large = encode_to_budget(&picture, 1 << 20, &config).unwrap();
+        assert_eq!(large.quality, 100.0);
+        assert_eq!(large.iterations, 1);
+
+        let budget = large.data.len() / 3;
+        let result = encode_to_budget(&picture, budget, &config).unwrap();
+        assert!(result.data.len() <= budget);
+        assert!(result.quality < 100.0);
+        assert!(result.iterations > 1);
+        assert!(result.psnr > 10.0 && result.psnr < large.psnr);
+        assert!(result.ssim > 0.0 && result.ssim <= large.ssim);
+        assert!(crate::decoder::Decoder::new(&result.data).is_ok());
+    }
+
+    #[test]
+    fn test_over_budget() {
+        let picture = noisy_picture();
+        let config = EncoderConfig::new().unwrap();
+        match encode_to_budget(&picture, 10, &config) {
+            Err(BudgetError::OverBudget { smallest }) => assert!(smallest > 10),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Composition of animation frames onto a full canvas, shared by the GIF and
+//! APNG conversions.
+
+use crate::anim_encoder::AnimEncodeError;
+use crate::encoder::Picture;
+
+/// A frame rectangle in canvas coordinates.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Rect {
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The composed RGBA pixels, transparent black where nothing was drawn.
+pub(crate) struct Canvas {
+    pub pixels: Vec<u8>,
+    width: usize,
+    height: usize,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32) -> Canvas {
+        Canvas {
+            pixels: vec![0; width as usize * height as usize * 4],
+            width: width as usize,
+            height: height as usize,
+        }
+    }
+
+    /// The part of the rectangle inside the canvas, as left, top, right and
+    /// bottom.
+    fn clip(&self, rect: Rect) -> (usize, usize, usize, usize) {
+        let left = (rect.left as usize).min(self.width);
+        let top = (rect.top as usize).min(self.height);
+        let right = (left + rect.width as usize).min(self.width);
+        let bottom = (top + rect.height as usize).min(self.height);
+        (left, top, right, bottom)
+    }
+
+    /// Calls `draw` with every canvas pixel inside the rectangle and its
+    /// position relative to the rectangle.
+    pub fn draw<F: FnMut(&mut [u8], usize, usize)>(&mut self, rect: Rect, mut draw: F) {
+        let (left, top, right, bottom) = self.clip(rect);
+        for y in top..bottom {
+            for x in left..right {
+                draw(
+                    &mut self.pixels[(y * self.width + x) * 4..][..4],
+                    x - left,
+                    y - top,
+                );
+            }
+        }
+    }
+
+    /// Clears the rectangle to transparent black.
+    pub fn clear(&mut self, rect: Rect) {
+        let (left, top, right, bottom) = self.clip(rect);
+        for y in top..bottom {
+            self.pixels[(y * self.width + left) * 4..(y * self.width + right) * 4].fill(0);
+        }
+    }
+
+    pub fn to_picture(&self) -> Result<Picture, AnimEncodeError> {
+        Ok(Picture::from_rgba(
+            &self.pixels,
+            self.width as u32,
+            self.height as u32,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clipped() {
+        let mut canvas = Canvas::new(3, 2);
+        let rect = Rect {
+            left: 1,
+            top: 1,
+            width: 5,
+            height: 5,
+        };
+        let mut drawn = Vec::new();
+        canvas.draw(rect, |pixel, x, y| {
+            pixel.copy_from_slice(&[1, 2, 3, 4]);
+            drawn.push((x, y));
+        });
+        assert_eq!(drawn, [(0, 0), (1, 0)]);
+        assert_eq!(&canvas.pixels[16..], &[1, 2, 3, 4, 1, 2, 3, 4]);
+        canvas.clear(Rect { left: 2, ..rect });
+        assert_eq!(&canvas.pixels[16..], &[1, 2, 3, 4, 0, 0, 0, 0]);
+        assert!(canvas.pixels[..16].iter().all(|&byte| byte == 0));
+    }
+}
@@ -0,0 +1,472 @@
+//! [`image`] codec backed by libwebp (the `image` feature).
+//!
+//! [`WebPDecoder`] implements `ImageDecoder`, [`WebPAnimationDecoder`]
+//! implements `AnimationDecoder` (with `demux` and `0_5`), and [`WebPEncoder`]
+//! implements `ImageEncoder` with lossy encoding, which the pure-Rust WebP
+//! encoder lacks.
+
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+
+use ::image::error::{
+    DecodingError, EncodingError, ImageFormatHint, LimitError, LimitErrorKind, ParameterError,
+    ParameterErrorKind, UnsupportedError, UnsupportedErrorKind,
+};
+use ::image::{ColorType, ExtendedColorType, ImageDecoder, ImageEncoder, ImageError, ImageFormat};
+use ::image::{ImageResult, Limits as ImageLimits};
+
+use crate::decoder::{self, DecodeError, Features, Limits, PixelLayout};
+use crate::encoder::{EncodeError, EncoderConfig, Picture};
+
+fn decoding_error(e: DecodeError) -> ImageError {
+    match e {
+        DecodeError::LimitExceeded(e) => {
+            let kind = match e.limit {
+                decoder::Limit::Pixels => LimitErrorKind::DimensionError,
+                _ => LimitErrorKind::InsufficientMemory,
+            };
+            ImageError::Limits(LimitError::from_kind(kind))
+        }
+        e => ImageError::Decoding(DecodingError::new(ImageFormat::WebP.into(), e)),
+    }
+}
+
+fn encoding_error(e: EncodeError) -> ImageError {
+    ImageError::Encoding(EncodingError::new(ImageFormat::WebP.into(), e))
+}
+
+/// Maps the limits of `image` onto [`Limits`].
+fn convert_limits(limits: &ImageLimits) -> Limits {
+    Limits {
+        max_total_bytes: limits.max_alloc,
+        ..Limits::none()
+    }
+}
+
+/// WebP decoder for `image`. Animations decode to their first frame.
+pub struct WebPDecoder {
+    data: Vec<u8>,
+    features: Features,
+    limits: Limits,
+}
+
+impl WebPDecoder {
+    /// Reads the whole input and its header.
+    pub fn new<R: Read>(mut reader: R) -> ImageResult<WebPDecoder> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        WebPDecoder::from_vec(data)
+    }
+
+    pub fn from_vec(data: Vec<u8>) -> ImageResult<WebPDecoder> {
+        let features = decoder::get_features(&data).map_err(decoding_error)?;
+        Ok(WebPDecoder {
+            data,
+            features,
+            limits: Limits::default(),
+        })
+    }
+
+    pub fn features(&self) -> Features {
+        self.features
+    }
+
+    fn layout(&self) -> PixelLayout {
+        if self.features.has_alpha {
+            PixelLayout::Rgba
+        } else {
+            PixelLayout::Rgb
+        }
+    }
+
+    #[cfg(all(feature = "demux", libwebp_0_5))]
+    fn first_frame(&self, buf: &mut [u8]) -> Result<(), DecodeError> {
+        use crate::anim::AnimDecoder;
+
+        let mut anim = AnimDecoder::with_limits(&self.data, self.limits)?;
+        let frame = match anim.next_frame()? {
+            Some(frame) => frame,
+            None => return Err(DecodeError::Status(crate::VP8_STATUS_BITSTREAM_ERROR)),
+        };
+        if self.has_alpha_channel() {
+            buf.copy_from_slice(frame.data);
+        } else {
+            for (dst, src) in buf.chunks_exact_mut(3).zip(frame.data.chunks_exact(4)) {
+                dst.copy_from_slice(&src[..3]);
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(all(feature = "demux", libwebp_0_5)))]
+    fn first_frame(&self, _buf: &mut [u8]) -> Result<(), DecodeError> {
+        Err(DecodeError::Status(crate::VP8_STATUS_UNSUPPORTED_FEATURE))
+    }
+
+    fn has_alpha_channel(&self) -> bool {
+        self.layout() == PixelLayout::Rgba
+    }
+
+    #[cfg(feature = "demux")]
+    fn chunk(&self, fourcc: &[u8; 4]) -> Option<Vec<u8>> {
+        use crate::demux::*;
+        use crate::mux_types::WebPData;
+
+        unsafe {
+            let data = WebPData {
+                bytes: self.data.as_ptr(),
+                size: self.data.len(),
+            };
+            let demux = WebPDemux(&data);
+            if demux.is_null() {
+                return None;
+            }
+            let mut iter: WebPChunkIterator = std::mem::zeroed();
+            let chunk = if WebPDemuxGetChunk(demux, fourcc.as_ptr() as *const _, 1, &mut iter) != 0
+            {
+                let chunk = iter.chunk;
+                let bytes = std::slice::from_raw_parts(chunk.bytes, chunk.size).to_vec();
+                WebPDemuxReleaseChunkIterator(&mut iter);
+                Some(bytes)
+            } else {
+                None
+            };
+            WebPDemuxDelete(demux);
+            chunk
+        }
+    }
+}
+
+impl ImageDecoder for WebPDecoder {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.features.width, self.features.height)
+    }
+
+    fn color_type(&self) -> ColorType {
+        if self.has_alpha_channel() {
+            ColorType::Rgba8
+        } else {
+            ColorType::Rgb8
+        }
+    }
+
+    #[cfg(feature = "demux")]
+    fn icc_profile(&mut self) -> ImageResult<Option<Vec<u8>>> {
+        Ok(self.chunk(b"ICCP"))
+    }
+
+    #[cfg(feature = "demux")]
+    fn exif_metadata(&mut self) -> ImageResult<Option<Vec<u8>>> {
+        Ok(self.chunk(b"EXIF"))
+    }
+
+    #[cfg(feature = "demux")]
+    fn xmp_metadata(&mut self) -> ImageResult<Option<Vec<u8>>> {
+        Ok(self.chunk(b"XMP "))
+    }
+
+    fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+        let result = if self.features.has_animation {
+            self.first_frame(buf)
+        } else {
+            let layout = self.layout();
+            let stride = self.features.width as usize * layout.bytes_per_pixel();
+            decoder::Decoder::with_limits(&self.data, self.limits)
+                .and_then(|decoder| decoder.decode_into(layout, buf, stride))
+        };
+        result.map_err(decoding_error)
+    }
+
+    fn read_image_boxed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+        (*self).read_image(buf)
+    }
+
+    fn set_limits(&mut self, limits: ImageLimits) -> ImageResult<()> {
+        limits.check_dimensions(self.features.width, self.features.height)?;
+        self.limits = convert_limits(&limits);
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "demux", libwebp_0_5))]
+pub use self::animation::WebPAnimationDecoder;
+
+#[cfg(all(feature = "demux", libwebp_0_5))]
+mod animation {
+    use std::num::NonZeroU32;
+    use std::time::Duration;
+
+    use ::image::metadata::LoopCount;
+    use ::image::RgbaImage;
+    use ::image::{AnimationDecoder, Delay, Frame, Frames, ImageResult, Limits as ImageLimits};
+
+    use super::{convert_limits, decoding_error};
+    use crate::anim::{AnimDecoder, AnimInfo};
+    use crate::decoder;
+
+    /// Animated WebP decoder for `image`, yielding composited RGBA canvases.
+    pub struct WebPAnimationDecoder<'a> {
+        decoder: AnimDecoder<'a>,
+    }
+
+    impl<'a> WebPAnimationDecoder<'a> {
+        pub fn new(data: &'a [u8]) -> ImageResult<WebPAnimationDecoder<'a>> {
+            WebPAnimationDecoder::with_limits(data, &ImageLimits::default())
+        }
+
+        pub fn with_limits(
+            data: &'a [u8],
+            limits: &ImageLimits,
+        ) -> ImageResult<WebPAnimationDecoder<'a>> {
+            let features = decoder::get_features(data).map_err(decoding_error)?;
+            limits.check_dimensions(features.width, features.height)?;
+            let decoder =
+                AnimDecoder::with_limits(data, convert_limits(limits)).map_err(decoding_error)?;
+            Ok(WebPAnimationDecoder { decoder })
+        }
+
+        pub fn info(&self) -> AnimInfo {
+            self.decoder.info()
+        }
+    }
+
+    impl<'a> AnimationDecoder<'a> for WebPAnimationDecoder<'a> {
+        fn into_frames(self) -> Frames<'a> {
+            let mut decoder = self.decoder;
+            let info = decoder.info();
+            let mut previous = Duration::from_millis(0);
+            Frames::new(Box::new(std::iter::from_fn(move || {
+                let frame = match decoder.next_frame() {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => return None,
+                    Err(e) => return Some(Err(decoding_error(e))),
+                };
+                let delay = frame.timestamp.checked_sub(previous).unwrap_or_default();
+                previous = frame.timestamp;
+                let buffer =
+                    RgbaImage::from_raw(info.canvas_width, info.canvas_height, frame.data.to_vec())
+                        .expect("canvas size");
+                Some(Ok(Frame::from_parts(
+                    buffer,
+                    0,
+                    0,
+                    Delay::from_saturating_duration(delay),
+                )))
+            })))
+        }
+
+        fn loop_count(&self) -> LoopCount {
+            match NonZeroU32::new(self.decoder.info().loop_count) {
+                Some(n) => LoopCount::Finite(n),
+                None => LoopCount::Infinite,
+            }
+        }
+    }
+}
+
+/// Compression settings of [`WebPEncoder`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EncoderOptions {
+    pub lossless: bool,
+    /// 0-100. For lossy encoding the visual quality, for lossless encoding
+    /// the effort spent on compression.
+    pub quality: f32,
+    /// Quality/speed trade-off, 0 (fast) to 6 (slower, better).
+    pub method: i32,
+}
+
+impl EncoderOptions {
+    pub fn lossy(quality: f32) -> EncoderOptions {
+        EncoderOptions {
+            lossless: false,
+            quality,
+            method: 4,
+        }
+    }
+
+    pub fn lossless() -> EncoderOptions {
+        EncoderOptions {
+            lossless: true,
+            quality: 75.0,
+            method: 4,
+        }
+    }
+
+    pub(crate) fn config(&self) -> Result<EncoderConfig, EncodeError> {
+        let mut config = EncoderConfig::lossy(self.quality)?;
+        config
+            .set_lossless(self.lossless)
+            .set_method(self.method as _);
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+impl Default for EncoderOptions {
+    /// Lossy encoding at quality 75.
+    fn default() -> EncoderOptions {
+        EncoderOptions::lossy(75.0)
+    }
+}
+
+/// WebP encoder for `image`. Accepts 8-bit gray, gray-alpha, RGB and RGBA.
+pub struct WebPEncoder<W: Write> {
+    writer: W,
+    options: EncoderOptions,
+}
+
+impl<W: Write> WebPEncoder<W> {
+    /// Lossy encoding at quality 75.
+    pub fn new(writer: W) -> WebPEncoder<W> {
+        WebPEncoder::with_options(writer, EncoderOptions::default())
+    }
+
+    pub fn with_options(writer: W, options: EncoderOptions) -> WebPEncoder<W> {
+        WebPEncoder { writer, options }
+    }
+
+    /// Encodes and writes an image, see [`ImageEncoder::write_image`].
+    pub fn encode(
+        mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ExtendedColorType,
+    ) -> ImageResult<()> {
+        let expected =
+            u64::from(width) * u64::from(height) * color_type.bits_per_pixel() as u64 / 8;
+        if buf.len() as u64 != expected {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            )));
+        }
+        let mut picture = match color_type {
+            ExtendedColorType::Rgb8 => Picture::from_rgb(buf, width, height),
+            ExtendedColorType::Rgba8 => Picture::from_rgba(buf, width, height),
+            ExtendedColorType::L8 => {
+                let rgb: Vec<u8> = buf.iter().flat_map(|&l| [l, l, l]).collect();
+                Picture::from_rgb(&rgb, width, height)
+            }
+            ExtendedColorType::La8 => {
+                let rgba: Vec<u8> = buf
+                    .chunks_exact(2)
+                    .flat_map(|la| [la[0], la[0], la[0], la[1]])
+                    .collect();
+                Picture::from_rgba(&rgba, width, height)
+            }
+            _ => {
+                return Err(ImageError::Unsupported(
+                    UnsupportedError::from_format_and_kind(
+                        ImageFormatHint::Exact(ImageFormat::WebP),
+                        UnsupportedErrorKind::Color(color_type),
+                    ),
+                ))
+            }
+        }
+        .map_err(encoding_error)?;
+        let config = self.options.config().map_err(encoding_error)?;
+        let webp = picture.encode(&config).map_err(encoding_error)?;
+        self.writer.write_all(&webp)?;
+        Ok(())
+    }
+}
+
+impl<W: Write> ImageEncoder for WebPEncoder<W> {
+    fn write_image(
+        self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ExtendedColorType,
+    ) -> ImageResult<()> {
+        self.encode(buf, width, height, color_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::image::{DynamicImage, RgbaImage};
+
+    fn test_image() -> RgbaImage {
+        RgbaImage::from_fn(24, 16, |x, y| {
+            ::image::Rgba([x as u8 * 10, y as u8 * 10, 0, 200])
+        })
+    }
+
+    #[test]
+    fn test_roundtrip_lossless() {
+        let image = test_image();
+        let mut webp = Vec::new();
+        WebPEncoder::with_options(&mut webp, EncoderOptions::lossless())
+            .write_image(&image, 24, 16, ExtendedColorType::Rgba8)
+            .unwrap();
+        let decoder = WebPDecoder::new(&webp[..]).unwrap();
+        assert_eq!(decoder.dimensions(), (24, 16));
+        assert_eq!(decoder.color_type(), ColorType::Rgba8);
+        let decoded = DynamicImage::from_decoder(decoder).unwrap();
+        assert_eq!(decoded.to_rgba8(), image);
+    }
+
+    #[test]
+    fn test_lossy_gray() {
+        let gray = vec![128u8; 24 * 16];
+        let mut webp = Vec::new();
+        WebPEncoder::new(&mut webp)
+            .write_image(&gray, 24, 16, ExtendedColorType::L8)
+            .unwrap();
+        let decoder = WebPDecoder::new(&webp[..]).unwrap();
+        assert_eq!(decoder.color_type(), ColorType::Rgb8);
+        let decoded = DynamicImage::from_decoder(decoder).unwrap().to_luma8();
+        assert!(decoded.pixels().all(|p| (p.0[0] as i32 - 128).abs() <= 2));
+    }
+
+    #[test]
+    fn test_limits() {
+        let image = test_image();
+        let mut webp = Vec::new();
+        WebPEncoder::new(&mut webp)
+            .write_image(&image, 24, 16, ExtendedColorType::Rgba8)
+            .unwrap();
+        let mut decoder = WebPDecoder::new(&webp[..]).unwrap();
+        let mut limits = ImageLimits::default();
+        limits.max_image_width = Some(16);
+        assert!(matches!(
+            decoder.set_limits(limits),
+            Err(ImageError::Limits(_))
+        ));
+        let mut limits = ImageLimits::default();
+        limits.max_alloc = Some(100);
+        decoder.set_limits(limits).unwrap();
+        assert!(matches!(
+            DynamicImage::from_decoder(decoder),
+            Err(ImageError::Limits(_))
+        ));
+    }
+
+    #[cfg(all(feature = "demux", libwebp_0_5))]
+    #[test]
+    fn test_animation() {
+        use ::image::AnimationDecoder;
+
+        let data = std::fs::read("./tests/animated.webp").unwrap();
+        let decoder = WebPAnimationDecoder::new(&data).unwrap();
+        let info = decoder.info();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(frames.len(), info.frame_count as usize);
+        let total: u32 = frames
+            .iter()
+            .map(|frame| {
+                let (numer, denom) = frame.delay().numer_denom_ms();
+                numer / denom
+            })
+            .sum();
+        assert_eq!(total as u128, info.duration.as_millis());
+
+        let still = WebPDecoder::new(&data[..]).unwrap();
+        assert!(still.features().has_animation);
+        let first = DynamicImage::from_decoder(still).unwrap();
+        assert_eq!(first.width(), info.canvas_width);
+    }
+}
@@ -0,0 +1,272 @@
+//! Colorspace conversion of pictures.
+//!
+//! A [`Picture`] holds either ARGB or YUV(A) 4:2:0 pixels, as selected by
+//! `use_argb`. Encoding converts it as needed, but converting up front with
+//! [`Picture::to_yuva`], [`Picture::to_yuva_dithered`] or
+//! [`Picture::to_yuva_smart`] chooses the conversion. The planes of the
+//! current colorspace can be borrowed with their strides.
+
+use std::slice;
+
+use crate::encode::*;
+use crate::encoder::{EncodeError, Picture};
+
+/// The pixel representation of a picture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Colorspace {
+    /// One `u32` per pixel (`use_argb` set).
+    Argb,
+    /// Y, U and V planes with 4:2:0 subsampling.
+    Yuv420,
+    /// Like `Yuv420`, plus an alpha plane.
+    Yuva420,
+}
+
+/// A borrowed plane, `stride` elements between rows.
+#[derive(Debug, Clone, Copy)]
+pub struct PlaneRef<'a, T> {
+    pub data: &'a [T],
+    pub width: u32,
+    pub height: u32,
+    pub stride: usize,
+}
+
+impl<'a, T> PlaneRef<'a, T> {
+    /// The `width` elements of row `y`.
+    pub fn row(&self, y: u32) -> &'a [T] {
+        &self.data[y as usize * self.stride..][..self.width as usize]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &'a [T]> + '_ {
+        (0..self.height).map(move |y| self.row(y))
+    }
+}
+
+/// Borrows `height` rows of `width` elements from `ptr`, if it is set.
+///
+/// Safety: `ptr` must point to a plane of at least these dimensions that
+/// outlives `'a`.
+unsafe fn plane<'a, T>(
+    ptr: *const T,
+    width: u32,
+    height: u32,
+    stride: i32,
+) -> Option<PlaneRef<'a, T>> {
+    if ptr.is_null() || stride < 0 || (height > 0 && (stride as u32) < width) {
+        return None;
+    }
+    let stride = stride as usize;
+    let len = if height == 0 {
+        0
+    } else {
+        stride * (height as usize - 1) + width as usize
+    };
+    Some(PlaneRef {
+        data: slice::from_raw_parts(ptr, len),
+        width,
+        height,
+        stride,
+    })
+}
+
+impl Picture {
+    /// The current colorspace.
+    pub fn colorspace(&self) -> Colorspace {
+        let raw = self.as_raw();
+        if raw.use_argb != 0 {
+            Colorspace::Argb
+        } else if raw.colorspace & WEBP_CSP_ALPHA_BIT != 0 {
+            Colorspace::Yuva420
+        } else {
+            Colorspace::Yuv420
+        }
+    }
+
+    /// Converts ARGB to YUV(A) 4:2:0 (`WebPPictureARGBToYUVA`). The alpha
+    /// plane is only kept if some pixel is not opaque.
+    ///
+    /// The picture must be in [`Colorspace::Argb`], otherwise this fails
+    /// with `VP8_ENC_ERROR_INVALID_CONFIGURATION`.
+    pub fn to_yuva(&mut self) -> Result<(), EncodeError> {
+        self.convert_argb(|raw| unsafe { WebPPictureARGBToYUVA(raw, WEBP_YUV420) })
+    }
+
+    /// Like [`Picture::to_yuva`], adding dithering of the given strength
+    /// (0 to 1) to the chroma planes (`WebPPictureARGBToYUVADithered`).
+    pub fn to_yuva_dithered(&mut self, dithering: f32) -> Result<(), EncodeError> {
+        self.convert_argb(|raw| unsafe {
+            WebPPictureARGBToYUVADithered(raw, WEBP_YUV420, dithering)
+        })
+    }
+
+    /// Like [`Picture::to_yuva`], with the slower, sharper RGB to YUV
+    /// conversion (`WebPPictureSmartARGBToYUVA`).
+    #[cfg(libwebp_0_5)]
+    #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "0_5")))]
+    pub fn to_yuva_smart(&mut self) -> Result<(), EncodeError> {
+        self.convert_argb(|raw| unsafe { WebPPictureSmartARGBToYUVA(raw) })
+    }
+
+    fn convert_argb(
+        &mut self,
+        convert: impl FnOnce(*mut WebPPicture) -> i32,
+    ) -> Result<(), EncodeError> {
+        if self.colorspace() != Colorspace::Argb {
+            return Err(EncodeError::Encoding(VP8_ENC_ERROR_INVALID_CONFIGURATION));
+        }
+        if convert(self.as_mut_ptr()) == 0 {
+            return Err(self.error());
+        }
+        Ok(())
+    }
+
+    /// Converts YUV(A) back to ARGB (`WebPPictureYUVAToARGB`).
+    ///
+    /// The picture must be in [`Colorspace::Yuv420`] or
+    /// [`Colorspace::Yuva420`], otherwise this fails with
+    /// `VP8_ENC_ERROR_INVALID_CONFIGURATION`.
+    pub fn to_argb(&mut self) -> Result<(), EncodeError> {
+        if self.colorspace() == Colorspace::Argb {
+            return Err(EncodeError::Encoding(VP8_ENC_ERROR_INVALID_CONFIGURATION));
+        }
+        if unsafe { WebPPictureYUVAToARGB(self.as_mut_ptr()) } == 0 {
+            return Err(self.error());
+        }
+        Ok(())
+    }
+
+    /// The ARGB pixels, if the picture is in [`Colorspace::Argb`].
+    pub fn argb(&self) -> Option<PlaneRef<'_, u32>> {
+        let raw = self.as_raw();
+        if raw.use_argb == 0 {
+            return None;
+        }
+        unsafe { plane(raw.argb, self.width(), self.height(), raw.argb_stride) }
+    }
+
+    /// The luma plane, if the picture is in a YUV colorspace.
+    pub fn y(&self) -> Option<PlaneRef<'_, u8>> {
+        let raw = self.as_raw();
+        if raw.use_argb != 0 {
+            return None;
+        }
+        unsafe { plane(raw.y, self.width(), self.height(), raw.y_stride) }
+    }
+
+    /// The U plane at half resolution, if the picture is in a YUV
+    /// colorspace.
+    pub fn u(&self) -> Option<PlaneRef<'_, u8>> {
+        let raw = self.as_raw();
+        if raw.use_argb != 0 {
+            return None;
+        }
+        let (width, height) = self.uv_dimensions();
+        unsafe { plane(raw.u, width, height, raw.uv_stride) }
+    }
+
+    /// The V plane at half resolution, if the picture is in a YUV
+    /// colorspace.
+    pub fn v(&self) -> Option<PlaneRef<'_, u8>> {
+        let raw = self.as_raw();
+        if raw.use_argb != 0 {
+            return None;
+        }
+        let (width, height) = self.uv_dimensions();
+        unsafe { plane(raw.v, width, height, raw.uv_stride) }
+    }
+
+    /// The alpha plane, if the picture is in [`Colorspace::Yuva420`].
+    pub fn a(&self) -> Option<PlaneRef<'_, u8>> {
+        if self.colorspace() != Colorspace::Yuva420 {
+            return None;
+        }
+        let raw = self.as_raw();
+        unsafe { plane(raw.a, self.width(), self.height(), raw.a_stride) }
+    }
+
+    fn uv_dimensions(&self) -> (u32, u32) {
+        ((self.width() + 1) >> 1, (self.height() + 1) >> 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{compare, Metric};
+
+    fn picture(alpha: u8) -> Picture {
+        // A red/blue edge between chroma samples, which plain 4:2:0 smears.
+        let rgba: Vec<u8> = (0..17 * 9)
+            .flat_map(|i| {
+                if i % 17 < 7 {
+                    vec![255, 0, 0, alpha]
+                } else {
+                    vec![0, 0, 255, alpha]
+                }
+            })
+            .collect();
+        Picture::from_rgba(&rgba, 17, 9).unwrap()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let source = picture(255);
+        let mut picture = source.try_clone().unwrap();
+        assert_eq!(picture.colorspace(), Colorspace::Argb);
+        let argb = picture.argb().unwrap();
+        assert_eq!(argb.row(0)[0], 0xffff_0000);
+        assert_eq!(argb.rows().count(), 9);
+        assert!(picture.y().is_none());
+
+        picture.to_yuva().unwrap();
+        assert_eq!(picture.colorspace(), Colorspace::Yuv420);
+        assert!(picture.argb().is_none() && picture.a().is_none());
+        let y = picture.y().unwrap();
+        assert_eq!((y.width, y.height), (17, 9));
+        assert_eq!(y.row(8).len(), 17);
+        let u = picture.u().unwrap();
+        assert_eq!((u.width, u.height), (9, 5));
+        assert_eq!(picture.v().unwrap().rows().count(), 5);
+        assert_eq!(
+            picture.to_yuva().unwrap_err(),
+            EncodeError::Encoding(VP8_ENC_ERROR_INVALID_CONFIGURATION)
+        );
+
+        picture.to_argb().unwrap();
+        assert_eq!(picture.colorspace(), Colorspace::Argb);
+        assert!(compare(&source, &picture, Metric::Psnr).unwrap().all > 20.0);
+        assert!(picture.to_argb().is_err());
+    }
+
+    #[test]
+    fn test_new_argb() {
+        let picture = Picture::new(5, 3).unwrap();
+        let argb = picture.argb().unwrap();
+        assert!(argb.rows().all(|row| row.iter().all(|&pixel| pixel == 0)));
+    }
+
+    #[test]
+    fn test_alpha_plane() {
+        let mut picture = picture(128);
+        picture.to_yuva_dithered(0.5).unwrap();
+        assert_eq!(picture.colorspace(), Colorspace::Yuva420);
+        assert!(picture
+            .a()
+            .unwrap()
+            .rows()
+            .all(|row| row.iter().all(|&a| a == 128)));
+    }
+
+    #[cfg(libwebp_0_5)]
+    #[test]
+    fn test_smart() {
+        let source = picture(255);
+        let mut plain = source.try_clone().unwrap();
+        let mut smart = source.try_clone().unwrap();
+        plain.to_yuva().unwrap();
+        smart.to_yuva_smart().unwrap();
+        assert!(plain.y().unwrap().rows().ne(smart.y().unwrap().rows()));
+        smart.to_argb().unwrap();
+        assert!(compare(&source, &smart, Metric::Psnr).unwrap().all > 15.0);
+    }
+}
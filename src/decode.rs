@@ -3,9 +3,11 @@ use std::ptr;
 
 // MAJOR(8b) + MINOR(8b)
 cfg_if! {
-    if #[cfg(feature = "1_1")] {
+    if #[cfg(libwebp_1_4)] {
+        pub const WEBP_DECODER_ABI_VERSION: c_int = 0x0210;
+    } else if #[cfg(libwebp_1_1)] {
         pub const WEBP_DECODER_ABI_VERSION: c_int = 0x0209;
-    } else if #[cfg(feature = "0_5")] {
+    } else if #[cfg(libwebp_0_5)] {
         pub const WEBP_DECODER_ABI_VERSION: c_int = 0x0208;
     } else {
         pub const WEBP_DECODER_ABI_VERSION: c_int = 0x0203;
@@ -194,17 +196,20 @@ pub unsafe extern "C" fn WebPIDecGetYUV(
     stride: *mut c_int,
     uv_stride: *mut c_int,
 ) -> *mut u8 {
-    WebPIDecGetYUVA(
-        idec,
-        last_y,
-        u,
-        v,
-        ptr::null_mut(),
-        width,
-        height,
-        stride,
-        uv_stride,
+    webp_call!(
         ptr::null_mut(),
+        WebPIDecGetYUVA(
+            idec,
+            last_y,
+            u,
+            v,
+            ptr::null_mut(),
+            width,
+            height,
+            stride,
+            uv_stride,
+            ptr::null_mut(),
+        )
     )
 }
 
@@ -224,23 +229,23 @@ pub struct WebPBitstreamFeatures {
     pub format: c_int,
     /// Unused for now. if true, using incremental decoding is not
     /// recommended.
-    #[cfg(not(feature = "0_5"))]
+    #[cfg(not(libwebp_0_5))]
     #[deprecated(note = "Removed as of libwebp 0.5.0")]
     pub no_incremental_decoding: c_int,
     /// Unused for now. TODO(later)
-    #[cfg(not(feature = "0_5"))]
+    #[cfg(not(libwebp_0_5))]
     #[deprecated(note = "Removed as of libwebp 0.5.0")]
     pub rotate: c_int,
     /// Unused for now. should be 0 for now. TODO(later)
-    #[cfg(not(feature = "0_5"))]
+    #[cfg(not(libwebp_0_5))]
     #[deprecated(note = "Removed as of libwebp 0.5.0")]
     pub uv_sampling: c_int,
     /// padding for later use
-    #[cfg(not(feature = "0_5"))]
+    #[cfg(not(libwebp_0_5))]
     #[doc(hidden)]
     pub pad: [u32; 2],
     /// padding for later use
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[doc(hidden)]
     pub pad: [u32; 5],
 }
@@ -276,19 +281,19 @@ pub struct WebPDecoderOptions {
     /// dithering strength (0=Off, 100=full)
     pub dithering_strength: c_int,
     /// if true, flip output vertically
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "0_5")))]
     pub flip: c_int,
     /// alpha dithering strength in [0..100]
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "0_5")))]
     pub alpha_dithering_strength: c_int,
     /// Unused for now. forced rotation (to be applied _last_)
-    #[cfg(not(feature = "0_5"))]
+    #[cfg(not(libwebp_0_5))]
     #[deprecated(note = "Removed as of libwebp 0.5.0")]
     pub force_rotation: c_int,
     /// Unused for now. if true, discard enhancement layer
-    #[cfg(not(feature = "0_5"))]
+    #[cfg(not(libwebp_0_5))]
     #[deprecated(note = "Removed as of libwebp 0.5.0")]
     pub no_enhancement: c_int,
     /// padding for later use
@@ -308,7 +313,7 @@ pub struct WebPDecoderConfig {
     pub options: WebPDecoderOptions,
 }
 
-extern "C" {
+webp_extern! {
     /// Return the decoder's version number, packed in hexadecimal using 8bits for
     /// each of major/minor/revision. E.g: v2.5.7 is 0x020507.
     pub fn WebPGetDecoderVersion() -> c_int;
@@ -454,7 +459,7 @@ extern "C" {
     ) -> *mut u8;
     /// Internal, version-checked, entry point
     #[doc(hidden)]
-    pub fn WebPInitDecBufferInternal(_: *mut WebPDecBuffer, _: c_int) -> c_int;
+    pub fn WebPInitDecBufferInternal(buffer: *mut WebPDecBuffer, version: c_int) -> c_int;
     /// Free any memory associated with the buffer. Must always be called last.
     /// Note: doesn't free the 'buffer' structure itself.
     pub fn WebPFreeDecBuffer(buffer: *mut WebPDecBuffer);
@@ -585,14 +590,19 @@ extern "C" {
     /// Internal, version-checked, entry point
     #[doc(hidden)]
     pub fn WebPGetFeaturesInternal(
-        _: *const u8,
-        _: usize,
-        _: *mut WebPBitstreamFeatures,
-        _: c_int,
+        data: *const u8,
+        data_size: usize,
+        features: *mut WebPBitstreamFeatures,
+        version: c_int,
     ) -> VP8StatusCode;
     /// Internal, version-checked, entry point
     #[doc(hidden)]
-    pub fn WebPInitDecoderConfigInternal(_: *mut WebPDecoderConfig, _: c_int) -> c_int;
+    pub fn WebPInitDecoderConfigInternal(config: *mut WebPDecoderConfig, version: c_int) -> c_int;
+    /// Returns true if 'config' is non-NULL and all configuration parameters are
+    /// within their valid ranges.
+    #[cfg(libwebp_1_4)]
+    #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "1_4")))]
+    pub fn WebPValidateDecoderConfig(config: *const WebPDecoderConfig) -> c_int;
     /// Instantiate a new incremental decoder object with the requested
     /// configuration. The bitstream can be passed using 'data' and 'data_size'
     /// parameter, in which case the features will be parsed and stored into
@@ -624,7 +634,10 @@ extern "C" {
 #[allow(non_snake_case)]
 #[inline]
 pub unsafe extern "C" fn WebPInitDecBuffer(buffer: *mut WebPDecBuffer) -> c_int {
-    WebPInitDecBufferInternal(buffer, WEBP_DECODER_ABI_VERSION)
+    webp_call!(
+        0,
+        WebPInitDecBufferInternal(buffer, WEBP_DECODER_ABI_VERSION)
+    )
 }
 
 /// Retrieve features from the bitstream. The *features structure is filled
@@ -645,7 +658,10 @@ pub unsafe extern "C" fn WebPGetFeatures(
     data_size: usize,
     features: *mut WebPBitstreamFeatures,
 ) -> VP8StatusCode {
-    WebPGetFeaturesInternal(data, data_size, features, WEBP_DECODER_ABI_VERSION)
+    webp_call!(
+        VP8_STATUS_INVALID_PARAM,
+        WebPGetFeaturesInternal(data, data_size, features, WEBP_DECODER_ABI_VERSION)
+    )
 }
 
 /// Initialize the configuration as empty. This function must always be
@@ -654,7 +670,10 @@ pub unsafe extern "C" fn WebPGetFeatures(
 #[allow(non_snake_case)]
 #[inline]
 pub unsafe extern "C" fn WebPInitDecoderConfig(config: *mut WebPDecoderConfig) -> c_int {
-    WebPInitDecoderConfigInternal(config, WEBP_DECODER_ABI_VERSION)
+    webp_call!(
+        0,
+        WebPInitDecoderConfigInternal(config, WEBP_DECODER_ABI_VERSION)
+    )
 }
 
 #[cfg(test)]
@@ -675,4 +694,74 @@ mod tests {
             WebPIDelete(idec);
         }
     }
+
+    #[cfg(libwebp_1_4)]
+    #[test]
+    fn test_validate_decoder_config() {
+        // An older libwebp loaded at runtime does not provide it.
+        #[cfg(feature = "dynamic")]
+        if !WebPValidateDecoderConfig::is_loaded() {
+            return;
+        }
+        unsafe {
+            let mut config = mem::zeroed();
+            assert!(WebPInitDecoderConfig(&mut config) != 0);
+            assert!(WebPValidateDecoderConfig(&config) != 0);
+        }
+    }
+
+    #[cfg(all(libwebp_threads, libwebp_0_5, target_os = "linux"))]
+    #[test]
+    fn test_decode_use_threads() {
+        use crate::encode::*;
+        use crate::types::WebPFree;
+        use std::fs;
+        use std::thread;
+
+        // Lossy and at least 512 pixels wide, so that libwebp decodes with a
+        // worker thread.
+        let (width, height) = (512, 64);
+        let rgb: Vec<u8> = (0..width * height * 3).map(|i| (i * 7) as u8).collect();
+        let data = unsafe {
+            let mut output = ptr::null_mut();
+            let size = WebPEncodeRGB(rgb.as_ptr(), width, height, width * 3, 75.0, &mut output);
+            assert!(size > 0);
+            let data = std::slice::from_raw_parts(output, size).to_vec();
+            WebPFree(output as *mut c_void);
+            data
+        };
+
+        // A thread started by libwebp inherits the name of the thread that
+        // started it.
+        let name = "webp-mt-probe";
+        let count = move || {
+            fs::read_dir("/proc/self/task")
+                .unwrap()
+                .filter(|task| {
+                    let comm = fs::read_to_string(task.as_ref().unwrap().path().join("comm"));
+                    comm.map_or(false, |comm| comm.trim_end() == name)
+                })
+                .count()
+        };
+        let threads = thread::Builder::new()
+            .name(name.into())
+            .spawn(move || unsafe {
+                let mut config = mem::zeroed();
+                assert!(WebPInitDecoderConfig(&mut config) != 0);
+                config.options.use_threads = 1;
+                let idec = WebPIDecode(ptr::null(), 0, &mut config);
+                assert!(!idec.is_null());
+                // Stop in the middle of the frame, while the worker is alive.
+                let status = WebPIAppend(idec, data.as_ptr(), data.len() / 2);
+                assert_eq!(status, VP8_STATUS_SUSPENDED);
+                let threads = count();
+                WebPIDelete(idec);
+                WebPFreeDecBuffer(&mut config.output);
+                threads
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(threads, 2);
+    }
 }
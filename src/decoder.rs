@@ -0,0 +1,457 @@
+//! Safe decoding of still images.
+//!
+//! [`Decoder`] reads the bitstream features first and checks them against
+//! [`Limits`] before any pixel buffer is allocated, so that a small file
+//! declaring a huge canvas is rejected cheaply.
+
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::os::raw::*;
+use std::time::Duration;
+
+use crate::decode::*;
+#[cfg(feature = "dynamic")]
+use crate::dynamic;
+use crate::version;
+
+/// Upper bounds on what a decoder may allocate. `None` means unlimited.
+///
+/// The default only limits the total output size to 512 MiB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum width × height of the image or animation canvas.
+    pub max_pixels: Option<u64>,
+    /// Maximum number of frames of an animation.
+    pub max_frames: Option<u32>,
+    /// Maximum size in bytes of the decoded pixels: the image for still
+    /// images, the two canvases kept by the decoder for animations.
+    pub max_total_bytes: Option<u64>,
+    /// Maximum total duration of an animation.
+    pub max_animation_duration: Option<Duration>,
+}
+
+impl Limits {
+    /// No limits at all.
+    pub fn none() -> Limits {
+        Limits {
+            max_pixels: None,
+            max_frames: None,
+            max_total_bytes: None,
+            max_animation_duration: None,
+        }
+    }
+
+    /// Checks a `width` × `height` image or canvas.
+    pub fn check_pixels(&self, width: u32, height: u32) -> Result<(), LimitExceeded> {
+        check(
+            Limit::Pixels,
+            u64::from(width) * u64::from(height),
+            self.max_pixels,
+        )
+    }
+
+    /// Checks the frame count of an animation.
+    pub fn check_frames(&self, frames: u32) -> Result<(), LimitExceeded> {
+        check(
+            Limit::Frames,
+            u64::from(frames),
+            self.max_frames.map(u64::from),
+        )
+    }
+
+    /// Checks the size in bytes of the decoded output.
+    pub fn check_total_bytes(&self, bytes: u64) -> Result<(), LimitExceeded> {
+        check(Limit::TotalBytes, bytes, self.max_total_bytes)
+    }
+
+    /// Checks the total duration of an animation.
+    pub fn check_animation_duration(&self, duration: Duration) -> Result<(), LimitExceeded> {
+        check(
+            Limit::AnimationDuration,
+            duration.as_millis() as u64,
+            self.max_animation_duration.map(|d| d.as_millis() as u64),
+        )
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_total_bytes: Some(512 * 1024 * 1024),
+            ..Limits::none()
+        }
+    }
+}
+
+fn check(limit: Limit, value: u64, max: Option<u64>) -> Result<(), LimitExceeded> {
+    match max {
+        Some(max) if value > max => Err(LimitExceeded { limit, value, max }),
+        _ => Ok(()),
+    }
+}
+
+/// One of the fields of [`Limits`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Limit {
+    Pixels,
+    Frames,
+    TotalBytes,
+    AnimationDuration,
+}
+
+/// An input exceeded one of the [`Limits`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitExceeded {
+    pub limit: Limit,
+    /// The value required by the input (milliseconds for durations).
+    pub value: u64,
+    /// The configured maximum, in the same unit.
+    pub max: u64,
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let what = match self.limit {
+            Limit::Pixels => "pixels",
+            Limit::Frames => "frames",
+            Limit::TotalBytes => "bytes",
+            Limit::AnimationDuration => "milliseconds",
+        };
+        write!(
+            f,
+            "input requires {} {}, but the limit is {}",
+            self.value, what, self.max
+        )
+    }
+}
+
+impl Error for LimitExceeded {}
+
+/// Errors of the safe decoders.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The linked libwebp is incompatible with the bindings.
+    Incompatible(version::Report),
+    /// libwebp could not be loaded.
+    #[cfg(feature = "dynamic")]
+    #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "dynamic")))]
+    Load(dynamic::Error),
+    /// libwebp reported an error.
+    Status(VP8StatusCode),
+    /// The input exceeds the configured [`Limits`].
+    LimitExceeded(LimitExceeded),
+    /// The output buffer passed to a `decode_into` method is too small.
+    BufferTooSmall,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Incompatible(report) => report.fmt(f),
+            #[cfg(feature = "dynamic")]
+            DecodeError::Load(e) => e.fmt(f),
+            DecodeError::Status(status) => f.write_str(match *status {
+                VP8_STATUS_OUT_OF_MEMORY => "out of memory",
+                VP8_STATUS_INVALID_PARAM => "invalid parameter",
+                VP8_STATUS_BITSTREAM_ERROR => "invalid WebP bitstream",
+                VP8_STATUS_UNSUPPORTED_FEATURE => "unsupported feature",
+                VP8_STATUS_SUSPENDED => "decoding suspended",
+                VP8_STATUS_USER_ABORT => "decoding aborted",
+                VP8_STATUS_NOT_ENOUGH_DATA => "not enough data",
+                _ => "unknown decoding error",
+            }),
+            DecodeError::LimitExceeded(e) => e.fmt(f),
+            DecodeError::BufferTooSmall => f.write_str("output buffer too small"),
+        }
+    }
+}
+
+impl Error for DecodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DecodeError::Incompatible(report) => Some(report),
+            #[cfg(feature = "dynamic")]
+            DecodeError::Load(e) => Some(e),
+            DecodeError::LimitExceeded(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<version::Report> for DecodeError {
+    fn from(report: version::Report) -> DecodeError {
+        DecodeError::Incompatible(report)
+    }
+}
+
+#[cfg(feature = "dynamic")]
+impl From<dynamic::Error> for DecodeError {
+    fn from(e: dynamic::Error) -> DecodeError {
+        DecodeError::Load(e)
+    }
+}
+
+impl From<LimitExceeded> for DecodeError {
+    fn from(e: LimitExceeded) -> DecodeError {
+        DecodeError::LimitExceeded(e)
+    }
+}
+
+/// Compression of the bitstream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// Undefined, or mixed in an animation.
+    Mixed,
+    Lossy,
+    Lossless,
+}
+
+/// Information from the bitstream header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Features {
+    pub width: u32,
+    pub height: u32,
+    pub has_alpha: bool,
+    pub has_animation: bool,
+    pub format: Format,
+}
+
+/// Retrieves the features of a WebP bitstream.
+pub fn get_features(data: &[u8]) -> Result<Features, DecodeError> {
+    #[cfg(feature = "dynamic")]
+    dynamic::load()?;
+    version::ensure()?;
+    let mut features: WebPBitstreamFeatures = unsafe { mem::zeroed() };
+    let status = unsafe { WebPGetFeatures(data.as_ptr(), data.len(), &mut features) };
+    if status != VP8_STATUS_OK {
+        return Err(DecodeError::Status(status));
+    }
+    Ok(Features {
+        width: features.width as u32,
+        height: features.height as u32,
+        has_alpha: features.has_alpha != 0,
+        has_animation: features.has_animation != 0,
+        format: match features.format {
+            1 => Format::Lossy,
+            2 => Format::Lossless,
+            _ => Format::Mixed,
+        },
+    })
+}
+
+/// Byte order of decoded pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PixelLayout {
+    Rgb,
+    Rgba,
+    Bgr,
+    Bgra,
+    Argb,
+}
+
+impl PixelLayout {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelLayout::Rgb | PixelLayout::Bgr => 3,
+            PixelLayout::Rgba | PixelLayout::Bgra | PixelLayout::Argb => 4,
+        }
+    }
+
+    pub(crate) fn mode(self) -> WEBP_CSP_MODE {
+        match self {
+            PixelLayout::Rgb => MODE_RGB,
+            PixelLayout::Rgba => MODE_RGBA,
+            PixelLayout::Bgr => MODE_BGR,
+            PixelLayout::Bgra => MODE_BGRA,
+            PixelLayout::Argb => MODE_ARGB,
+        }
+    }
+}
+
+/// A decoded image with tightly packed rows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub layout: PixelLayout,
+    pub data: Vec<u8>,
+}
+
+/// Decoder of still WebP images.
+#[derive(Debug, Clone)]
+pub struct Decoder<'a> {
+    data: &'a [u8],
+    features: Features,
+    limits: Limits,
+    use_threads: bool,
+}
+
+impl<'a> Decoder<'a> {
+    /// Reads the header of `data` and checks it against the default [`Limits`].
+    pub fn new(data: &'a [u8]) -> Result<Decoder<'a>, DecodeError> {
+        Decoder::with_limits(data, Limits::default())
+    }
+
+    /// Reads the header of `data` and checks it against `limits`.
+    pub fn with_limits(data: &'a [u8], limits: Limits) -> Result<Decoder<'a>, DecodeError> {
+        let features = get_features(data)?;
+        limits.check_pixels(features.width, features.height)?;
+        Ok(Decoder {
+            data,
+            features,
+            limits,
+            use_threads: false,
+        })
+    }
+
+    pub fn features(&self) -> Features {
+        self.features
+    }
+
+    /// Enables multi-threaded decoding (see `WebPDecoderOptions.use_threads`).
+    pub fn use_threads(&mut self, use_threads: bool) -> &mut Self {
+        self.use_threads = use_threads;
+        self
+    }
+
+    /// Decodes the image into a new buffer.
+    pub fn decode(&self, layout: PixelLayout) -> Result<Image, DecodeError> {
+        let stride = self.features.width as usize * layout.bytes_per_pixel();
+        let size = self.output_size(stride)?;
+        let mut data = vec![0; size];
+        self.decode_into(layout, &mut data, stride)?;
+        Ok(Image {
+            width: self.features.width,
+            height: self.features.height,
+            layout,
+            data,
+        })
+    }
+
+    /// Decodes the image into `buffer`, whose rows are `stride` bytes apart.
+    pub fn decode_into(
+        &self,
+        layout: PixelLayout,
+        buffer: &mut [u8],
+        stride: usize,
+    ) -> Result<(), DecodeError> {
+        let row = self.features.width as usize * layout.bytes_per_pixel();
+        if stride < row || stride > c_int::MAX as usize {
+            return Err(DecodeError::BufferTooSmall);
+        }
+        // The last row needs no padding.
+        let size = self.output_size(stride)?;
+        if buffer.len() < size - (stride - row).min(size) {
+            return Err(DecodeError::BufferTooSmall);
+        }
+        unsafe {
+            let mut config: WebPDecoderConfig = mem::zeroed();
+            if WebPInitDecoderConfig(&mut config) == 0 {
+                return Err(DecodeError::Status(VP8_STATUS_INVALID_PARAM));
+            }
+            config.options.use_threads = self.use_threads as c_int;
+            config.output.colorspace = layout.mode();
+            config.output.is_external_memory = 1;
+            config.output.u.RGBA = WebPRGBABuffer {
+                rgba: buffer.as_mut_ptr(),
+                stride: stride as c_int,
+                size: buffer.len(),
+            };
+            let status = WebPDecode(self.data.as_ptr(), self.data.len(), &mut config);
+            WebPFreeDecBuffer(&mut config.output);
+            if status != VP8_STATUS_OK {
+                return Err(DecodeError::Status(status));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the size of an output buffer with the given stride against the
+    /// limits.
+    pub(crate) fn output_size(&self, stride: usize) -> Result<usize, DecodeError> {
+        let size = stride as u64 * u64::from(self.features.height);
+        self.limits.check_total_bytes(size)?;
+        if size > isize::MAX as u64 {
+            return Err(DecodeError::Status(VP8_STATUS_OUT_OF_MEMORY));
+        }
+        Ok(size as usize)
+    }
+}
+
+/// Decodes a still image with the default [`Limits`].
+pub fn decode(data: &[u8], layout: PixelLayout) -> Result<Image, DecodeError> {
+    Decoder::new(data)?.decode(layout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::*;
+    #[cfg(libwebp_0_5)]
+    use crate::WebPFree;
+    #[cfg(not(libwebp_0_5))]
+    use libc::free as WebPFree;
+
+    pub(crate) fn encode_test_image(width: c_int, height: c_int) -> Vec<u8> {
+        let rgba: Vec<u8> = (0..width * height * 4).map(|i| (i % 251) as u8).collect();
+        unsafe {
+            let mut output = std::ptr::null_mut();
+            let size = WebPEncodeLosslessRGBA(rgba.as_ptr(), width, height, width * 4, &mut output);
+            assert!(size > 0);
+            let webp = std::slice::from_raw_parts(output, size).to_vec();
+            WebPFree(output as *mut c_void);
+            webp
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        let webp = encode_test_image(16, 8);
+        let decoder = Decoder::new(&webp).unwrap();
+        let features = decoder.features();
+        assert_eq!((features.width, features.height), (16, 8));
+        assert_eq!(features.format, Format::Lossless);
+        let image = decoder.decode(PixelLayout::Rgba).unwrap();
+        assert_eq!(image.data.len(), 16 * 8 * 4);
+        assert_eq!(&image.data[..4], &[0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_limits() {
+        let webp = encode_test_image(16, 8);
+        let limits = Limits {
+            max_pixels: Some(100),
+            ..Limits::none()
+        };
+        match Decoder::with_limits(&webp, limits) {
+            Err(DecodeError::LimitExceeded(e)) => {
+                assert_eq!(e.limit, Limit::Pixels);
+                assert_eq!((e.value, e.max), (128, 100));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        let limits = Limits {
+            max_total_bytes: Some(16 * 8 * 3),
+            ..Limits::none()
+        };
+        let decoder = Decoder::with_limits(&webp, limits).unwrap();
+        assert!(decoder.decode(PixelLayout::Rgb).is_ok());
+        assert_eq!(
+            decoder.decode(PixelLayout::Rgba).unwrap_err(),
+            DecodeError::LimitExceeded(LimitExceeded {
+                limit: Limit::TotalBytes,
+                value: 16 * 8 * 4,
+                max: 16 * 8 * 3,
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(
+            get_features(b"RIFF\0\0\0\0WEBP"),
+            Err(DecodeError::Status(VP8_STATUS_BITSTREAM_ERROR))
+        );
+    }
+}
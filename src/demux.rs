@@ -1,7 +1,7 @@
 use std::os::raw::*;
 use std::ptr;
 
-#[cfg(feature = "0_5")]
+#[cfg(libwebp_0_5)]
 use crate::decode::*;
 use crate::mux_types::*;
 
@@ -9,7 +9,7 @@ use crate::mux_types::*;
 pub const WEBP_DEMUX_ABI_VERSION: c_int = WEBP_DEMUX_ABI_VERSION_INTERNAL;
 
 cfg_if! {
-    if #[cfg(feature = "0_5")] {
+    if #[cfg(libwebp_0_5)] {
         const WEBP_DEMUX_ABI_VERSION_INTERNAL: c_int = 0x0107;
     } else {
         const WEBP_DEMUX_ABI_VERSION_INTERNAL: c_int = 0x0101;
@@ -53,10 +53,10 @@ pub const WEBP_FF_FRAME_COUNT: WebPFormatFeature = 5;
 pub struct WebPIterator {
     pub frame_num: c_int,
     pub num_frames: c_int,
-    #[cfg(not(feature = "0_5"))]
+    #[cfg(not(libwebp_0_5))]
     #[deprecated(note = "Removed as of libwebp 0.5.0")]
     pub fragment_num: c_int,
-    #[cfg(not(feature = "0_5"))]
+    #[cfg(not(libwebp_0_5))]
     #[deprecated(note = "Removed as of libwebp 0.5.0")]
     pub num_fragments: c_int,
     pub x_offset: c_int,
@@ -88,7 +88,7 @@ pub struct WebPChunkIterator {
     pub private_: *mut c_void,
 }
 
-#[cfg(all(feature = "0_5", feature = "extern-types"))]
+#[cfg(all(libwebp_0_5, feature = "extern-types"))]
 extern "C" {
     #[cfg_attr(
         feature = "__doc_cfg",
@@ -97,7 +97,7 @@ extern "C" {
     pub type WebPAnimDecoder;
 }
 
-#[cfg(all(feature = "0_5", not(feature = "extern-types")))]
+#[cfg(all(libwebp_0_5, not(feature = "extern-types")))]
 #[cfg_attr(
     feature = "__doc_cfg",
     doc(cfg(all(feature = "demux", feature = "0_5")))
@@ -105,7 +105,7 @@ extern "C" {
 #[repr(C)]
 pub struct WebPAnimDecoder(c_void);
 
-#[cfg(feature = "0_5")]
+#[cfg(libwebp_0_5)]
 #[cfg_attr(
     feature = "__doc_cfg",
     doc(cfg(all(feature = "demux", feature = "0_5")))
@@ -119,7 +119,7 @@ pub struct WebPAnimDecoderOptions {
     pub padding: [u32; 7],
 }
 
-#[cfg(feature = "0_5")]
+#[cfg(libwebp_0_5)]
 #[cfg_attr(
     feature = "__doc_cfg",
     doc(cfg(all(feature = "demux", feature = "0_5")))
@@ -136,16 +136,16 @@ pub struct WebPAnimInfo {
     pub pad: [u32; 4],
 }
 
-extern "C" {
+webp_extern! {
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "demux")))]
     pub fn WebPGetDemuxVersion() -> c_int;
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "demux")))]
     #[doc(hidden)]
     pub fn WebPDemuxInternal(
-        _: *const WebPData,
-        _: c_int,
-        _: *mut WebPDemuxState,
-        _: c_int,
+        data: *const WebPData,
+        allow_partial: c_int,
+        state: *mut WebPDemuxState,
+        version: c_int,
     ) -> *mut WebPDemuxer;
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "demux")))]
     pub fn WebPDemuxDelete(dmux: *mut WebPDemuxer);
@@ -161,7 +161,7 @@ extern "C" {
     pub fn WebPDemuxNextFrame(iter: *mut WebPIterator) -> c_int;
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "demux")))]
     pub fn WebPDemuxPrevFrame(iter: *mut WebPIterator) -> c_int;
-    #[cfg(not(feature = "0_5"))]
+    #[cfg(not(libwebp_0_5))]
     #[cfg_attr(
         feature = "__doc_cfg",
         doc(cfg(all(feature = "demux", feature = "0_5")))
@@ -183,31 +183,34 @@ extern "C" {
     pub fn WebPDemuxPrevChunk(iter: *mut WebPChunkIterator) -> c_int;
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "demux")))]
     pub fn WebPDemuxReleaseChunkIterator(iter: *mut WebPChunkIterator);
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(
         feature = "__doc_cfg",
         doc(cfg(all(feature = "demux", feature = "0_5")))
     )]
     #[doc(hidden)]
-    pub fn WebPAnimDecoderOptionsInitInternal(_: *mut WebPAnimDecoderOptions, _: c_int) -> c_int;
-    #[cfg(feature = "0_5")]
+    pub fn WebPAnimDecoderOptionsInitInternal(
+        dec_options: *mut WebPAnimDecoderOptions,
+        version: c_int,
+    ) -> c_int;
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(
         feature = "__doc_cfg",
         doc(cfg(all(feature = "demux", feature = "0_5")))
     )]
     #[doc(hidden)]
     pub fn WebPAnimDecoderNewInternal(
-        _: *const WebPData,
-        _: *const WebPAnimDecoderOptions,
-        _: c_int,
+        webp_data: *const WebPData,
+        dec_options: *const WebPAnimDecoderOptions,
+        version: c_int,
     ) -> *mut WebPAnimDecoder;
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(
         feature = "__doc_cfg",
         doc(cfg(all(feature = "demux", feature = "0_5")))
     )]
     pub fn WebPAnimDecoderGetInfo(dec: *const WebPAnimDecoder, info: *mut WebPAnimInfo) -> c_int;
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(
         feature = "__doc_cfg",
         doc(cfg(all(feature = "demux", feature = "0_5")))
@@ -217,25 +220,25 @@ extern "C" {
         buf: *mut *mut u8,
         timestamp: *mut c_int,
     ) -> c_int;
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(
         feature = "__doc_cfg",
         doc(cfg(all(feature = "demux", feature = "0_5")))
     )]
     pub fn WebPAnimDecoderHasMoreFrames(dec: *const WebPAnimDecoder) -> c_int;
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(
         feature = "__doc_cfg",
         doc(cfg(all(feature = "demux", feature = "0_5")))
     )]
     pub fn WebPAnimDecoderReset(dec: *mut WebPAnimDecoder);
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(
         feature = "__doc_cfg",
         doc(cfg(all(feature = "demux", feature = "0_5")))
     )]
     pub fn WebPAnimDecoderGetDemuxer(dec: *const WebPAnimDecoder) -> *const WebPDemuxer;
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(
         feature = "__doc_cfg",
         doc(cfg(all(feature = "demux", feature = "0_5")))
@@ -247,7 +250,10 @@ extern "C" {
 #[allow(non_snake_case)]
 #[inline]
 pub unsafe extern "C" fn WebPDemux(data: *const WebPData) -> *mut WebPDemuxer {
-    WebPDemuxInternal(data, 0, ptr::null_mut(), WEBP_DEMUX_ABI_VERSION)
+    webp_call!(
+        ptr::null_mut(),
+        WebPDemuxInternal(data, 0, ptr::null_mut(), WEBP_DEMUX_ABI_VERSION)
+    )
 }
 
 #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "demux")))]
@@ -257,10 +263,13 @@ pub unsafe extern "C" fn WebPDemuxPartial(
     data: *const WebPData,
     state: *mut WebPDemuxState,
 ) -> *mut WebPDemuxer {
-    WebPDemuxInternal(data, 1, state, WEBP_DEMUX_ABI_VERSION)
+    webp_call!(
+        ptr::null_mut(),
+        WebPDemuxInternal(data, 1, state, WEBP_DEMUX_ABI_VERSION)
+    )
 }
 
-#[cfg(feature = "0_5")]
+#[cfg(libwebp_0_5)]
 #[cfg_attr(
     feature = "__doc_cfg",
     doc(cfg(all(feature = "demux", feature = "0_5")))
@@ -270,10 +279,13 @@ pub unsafe extern "C" fn WebPDemuxPartial(
 pub unsafe extern "C" fn WebPAnimDecoderOptionsInit(
     dec_options: *mut WebPAnimDecoderOptions,
 ) -> c_int {
-    WebPAnimDecoderOptionsInitInternal(dec_options, WEBP_DEMUX_ABI_VERSION)
+    webp_call!(
+        0,
+        WebPAnimDecoderOptionsInitInternal(dec_options, WEBP_DEMUX_ABI_VERSION)
+    )
 }
 
-#[cfg(feature = "0_5")]
+#[cfg(libwebp_0_5)]
 #[cfg_attr(
     feature = "__doc_cfg",
     doc(cfg(all(feature = "demux", feature = "0_5")))
@@ -284,7 +296,10 @@ pub unsafe extern "C" fn WebPAnimDecoderNew(
     webp_data: *const WebPData,
     dec_options: *const WebPAnimDecoderOptions,
 ) -> *mut WebPAnimDecoder {
-    WebPAnimDecoderNewInternal(webp_data, dec_options, WEBP_DEMUX_ABI_VERSION)
+    webp_call!(
+        ptr::null_mut(),
+        WebPAnimDecoderNewInternal(webp_data, dec_options, WEBP_DEMUX_ABI_VERSION)
+    )
 }
 
 #[cfg(test)]
@@ -315,7 +330,7 @@ mod tests {
     }
 
     #[test]
-    #[cfg(all(feature = "0_5", feature = "demux"))]
+    #[cfg(all(libwebp_0_5, feature = "demux"))]
     fn test_anim_decoder() {
         use std::fs::File;
         use std::io::prelude::*;
@@ -0,0 +1,282 @@
+//! Loading libwebp at runtime (the `dynamic` feature).
+//!
+//! With `dynamic`, the crate is not linked against libwebp. The functions are
+//! resolved from shared libraries opened by [`load`] or [`load_from`], or by
+//! the first call into libwebp if neither was called. Opening only requires
+//! the version getters, so a library older than the compiled API level still
+//! loads; [`api_levels`] reports which of the `0_5`/.../`1_5` API levels it
+//! supports. The safe wrappers load libwebp themselves and return [`Error`]
+//! on failure.
+//!
+//! Calling a function that the loaded library does not provide panics. Every
+//! function has a companion module of the same name to check first: e.g.
+//! `WebPMalloc::is_loaded()`, or `WebPMalloc::get()` which returns the function
+//! pointer or [`Error::MissingSymbol`].
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::os::raw::c_int;
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use libloading::Library;
+
+use crate::version::{self, Report, Version};
+
+/// Error returned when libwebp or one of its functions cannot be loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// None of the candidate file names of a library could be opened. Holds
+    /// the message of the last attempt.
+    Load(String),
+    /// The loaded libraries do not export the function.
+    MissingSymbol(&'static str),
+    /// The loaded libwebp is incompatible with the bindings.
+    Incompatible(Report),
+    /// [`load_from`] was called after libwebp was already loaded.
+    AlreadyLoaded,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Load(e) => write!(f, "failed to load libwebp: {}", e),
+            Error::MissingSymbol(name) => write!(f, "the loaded libwebp does not provide {}", name),
+            Error::Incompatible(report) => report.fmt(f),
+            Error::AlreadyLoaded => f.write_str("libwebp is already loaded"),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Incompatible(report) => Some(report),
+            _ => None,
+        }
+    }
+}
+
+/// The libraries to open: base name, and the major versions of their sonames.
+const LIBRARIES: &[(&str, &[u32])] = &[
+    ("webp", &[7, 6]),
+    #[cfg(feature = "demux")]
+    ("webpdemux", &[2, 1]),
+    #[cfg(feature = "mux")]
+    ("webpmux", &[3, 2]),
+    #[cfg(feature = "sharpyuv")]
+    ("sharpyuv", &[0]),
+];
+
+/// Functions that every libwebp provides and that [`version::check`] calls,
+/// looked up when the libraries are opened. All others are resolved on first
+/// use.
+const BASELINE: &[&str] = &[
+    "WebPGetDecoderVersion",
+    "WebPGetEncoderVersion",
+    #[cfg(feature = "demux")]
+    "WebPGetDemuxVersion",
+    #[cfg(feature = "mux")]
+    "WebPGetMuxVersion",
+];
+
+/// API levels and functions that must be present for each of them, in
+/// addition to a matching `WebPGetDecoderVersion()`.
+const API_LEVELS: &[(Version, &[&str])] = &[
+    (
+        Version::new(0, 5, 0),
+        &[
+            "WebPFree",
+            "WebPConfigLosslessPreset",
+            "WebPMemoryWriterClear",
+            "WebPPictureSmartARGBToYUVA",
+        ],
+    ),
+    (Version::new(0, 6, 0), &["WebPPlaneDistortion"]),
+    (Version::new(1, 1, 0), &["WebPMalloc"]),
+    (Version::new(1, 2, 0), &[]),
+    (Version::new(1, 3, 0), &[]),
+    (Version::new(1, 4, 0), &["WebPValidateDecoderConfig"]),
+    (Version::new(1, 5, 0), &[]),
+];
+
+struct Libraries {
+    libraries: Vec<Library>,
+}
+
+impl Libraries {
+    fn find(&self, name: &str) -> Option<usize> {
+        self.libraries.iter().find_map(|library| unsafe {
+            library
+                .get::<unsafe extern "C" fn()>(name.as_bytes())
+                .ok()
+                .map(|f| *f as usize)
+        })
+    }
+}
+
+static LOADED: AtomicPtr<Libraries> = AtomicPtr::new(ptr::null_mut());
+
+/// File names to try for a library, most specific last.
+fn file_names(name: &str, majors: &[u32]) -> Vec<String> {
+    let mut names = vec![libloading::library_filename(name)
+        .to_string_lossy()
+        .into_owned()];
+    for major in majors {
+        if cfg!(windows) {
+            names.push(format!("lib{}-{}.dll", name, major));
+        } else if cfg!(target_os = "macos") {
+            names.push(format!("lib{}.{}.dylib", name, major));
+        } else {
+            names.push(format!("lib{}.so.{}", name, major));
+        }
+    }
+    if cfg!(windows) {
+        names.push(format!("lib{}.dll", name));
+    }
+    names
+}
+
+fn open(dir: Option<&Path>) -> Result<&'static Libraries, Error> {
+    let mut libraries = Vec::new();
+    for &(name, majors) in LIBRARIES {
+        let mut last_error = None;
+        for file_name in file_names(name, majors) {
+            let path = match dir {
+                Some(dir) => dir.join(&file_name),
+                None => file_name.into(),
+            };
+            match unsafe { Library::new(&path) } {
+                Ok(library) => {
+                    libraries.push(library);
+                    last_error = None;
+                    break;
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        if let Some(e) = last_error {
+            return Err(Error::Load(e.to_string()));
+        }
+    }
+    let libraries = Libraries { libraries };
+    if let Some(&name) = BASELINE.iter().find(|name| libraries.find(name).is_none()) {
+        return Err(Error::MissingSymbol(name));
+    }
+    let new = Box::into_raw(Box::new(libraries));
+    match LOADED.compare_exchange(ptr::null_mut(), new, Ordering::AcqRel, Ordering::Acquire) {
+        Ok(_) => Ok(unsafe { &*new }),
+        Err(existing) => {
+            // Another thread won the race; keep its libraries.
+            drop(unsafe { Box::from_raw(new) });
+            if dir.is_some() {
+                Err(Error::AlreadyLoaded)
+            } else {
+                Ok(unsafe { &*existing })
+            }
+        }
+    }
+}
+
+/// Opens libwebp (and libwebpdemux, libwebpmux and libsharpyuv, depending on
+/// the features) from the system's library search path, and checks it with
+/// [`version::ensure`]. Only checks if it is already loaded.
+pub fn load() -> Result<(), Error> {
+    libraries()?;
+    version::ensure().map_err(Error::Incompatible)
+}
+
+/// Opens the libraries from `dir` instead of the search path. Must be called
+/// before any other use of libwebp.
+pub fn load_from<P: AsRef<Path>>(dir: P) -> Result<(), Error> {
+    if is_loaded() {
+        return Err(Error::AlreadyLoaded);
+    }
+    open(Some(dir.as_ref()))?;
+    version::ensure().map_err(Error::Incompatible)
+}
+
+/// Whether libwebp has been loaded.
+pub fn is_loaded() -> bool {
+    !LOADED.load(Ordering::Acquire).is_null()
+}
+
+fn libraries() -> Result<&'static Libraries, Error> {
+    let loaded = LOADED.load(Ordering::Acquire);
+    if loaded.is_null() {
+        open(None)
+    } else {
+        Ok(unsafe { &*loaded })
+    }
+}
+
+/// Looks up a function, caching its address in `cache`.
+#[doc(hidden)]
+pub fn resolve(cache: &AtomicUsize, name: &'static str) -> Result<usize, Error> {
+    let address = cache.load(Ordering::Relaxed);
+    if address != 0 {
+        return Ok(address);
+    }
+    let address = libraries()?.find(name).ok_or(Error::MissingSymbol(name))?;
+    cache.store(address, Ordering::Relaxed);
+    Ok(address)
+}
+
+/// The API levels supported by the loaded libwebp, oldest first. A level is
+/// supported if the library is recent enough and exports the functions the
+/// level introduced.
+pub fn api_levels() -> Result<Vec<Version>, Error> {
+    let libraries = libraries()?;
+    let get_version = libraries
+        .find("WebPGetDecoderVersion")
+        .ok_or(Error::MissingSymbol("WebPGetDecoderVersion"))?;
+    let get_version: unsafe extern "C" fn() -> c_int =
+        unsafe { std::mem::transmute::<usize, unsafe extern "C" fn() -> c_int>(get_version) };
+    let version = Version::from_packed(unsafe { get_version() });
+    Ok(API_LEVELS
+        .iter()
+        .take_while(|&&(level, functions)| {
+            version >= level && functions.iter().all(|name| libraries.find(name).is_some())
+        })
+        .map(|&(level, _)| level)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_levels() {
+        load().unwrap();
+        assert!(is_loaded());
+        let levels = api_levels().unwrap();
+        assert!(levels.contains(&Version::new(0, 5, 0)), "{:?}", levels);
+        assert!(crate::WebPGetDecoderVersion::is_loaded());
+        assert!(matches!(load_from("."), Err(Error::AlreadyLoaded)));
+    }
+
+    #[cfg(libwebp_1_4)]
+    #[test]
+    fn test_level_gated() {
+        load().unwrap();
+        let supported = api_levels().unwrap().contains(&Version::new(1, 4, 0));
+        match crate::WebPValidateDecoderConfig::get() {
+            Ok(_) => assert!(supported),
+            Err(e) => {
+                assert!(!supported);
+                assert_eq!(e, Error::MissingSymbol("WebPValidateDecoderConfig"));
+            }
+        }
+    }
+
+    #[test]
+    fn test_missing_symbol() {
+        static CACHE: AtomicUsize = AtomicUsize::new(0);
+        match resolve(&CACHE, "WebPNoSuchFunction") {
+            Err(Error::MissingSymbol(name)) => assert_eq!(name, "WebPNoSuchFunction"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -1,11 +1,13 @@
 use std::os::raw::*;
 
 cfg_if! {
-    if #[cfg(feature = "1_1")] {
+    if #[cfg(libwebp_1_5)] {
+        pub const WEBP_ENCODER_ABI_VERSION: c_int = 0x0210;
+    } else if #[cfg(libwebp_1_1)] {
         pub const WEBP_ENCODER_ABI_VERSION: c_int = 0x020F;
-    } else if #[cfg(feature = "0_6")] {
+    } else if #[cfg(libwebp_0_6)] {
         pub const WEBP_ENCODER_ABI_VERSION: c_int = 0x020E;
-    } else if #[cfg(feature = "0_5")] {
+    } else if #[cfg(libwebp_0_5)] {
         pub const WEBP_ENCODER_ABI_VERSION: c_int = 0x0209;
     } else {
         pub const WEBP_ENCODER_ABI_VERSION: c_int = 0x0202;
@@ -48,31 +50,31 @@ pub struct WebPConfig {
     pub emulate_jpeg_size: c_int,
     pub thread_level: c_int,
     pub low_memory: c_int,
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "0_5")))]
     pub near_lossless: c_int,
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "0_5")))]
     pub exact: c_int,
-    #[cfg(feature = "0_6")]
+    #[cfg(libwebp_0_6)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "0_6")))]
     pub use_delta_palette: c_int,
-    #[cfg(feature = "0_6")]
+    #[cfg(libwebp_0_6)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "0_6")))]
     pub use_sharp_yuv: c_int,
-    #[cfg(feature = "1_2")]
+    #[cfg(libwebp_1_2)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "1_2")))]
     pub qmin: c_int,
-    #[cfg(feature = "1_2")]
+    #[cfg(libwebp_1_2)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "1_2")))]
     pub qmax: c_int,
-    #[cfg(not(feature = "0_5"))]
+    #[cfg(not(libwebp_0_5))]
     #[doc(hidden)]
     pub pad: [u32; 5],
-    #[cfg(all(feature = "0_5", not(feature = "0_6")))]
+    #[cfg(all(libwebp_0_5, not(libwebp_0_6)))]
     #[doc(hidden)]
     pub pad: [u32; 3],
-    #[cfg(all(feature = "0_6", not(feature = "1_2")))]
+    #[cfg(all(libwebp_0_6, not(libwebp_1_2)))]
     #[doc(hidden)]
     pub pad: [u32; 2],
 }
@@ -107,18 +109,24 @@ pub struct WebPAuxStats {
     pub cache_bits: c_int,
     pub palette_size: c_int,
     pub lossless_size: c_int,
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "0_5")))]
     pub lossless_hdr_size: c_int,
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "0_5")))]
     pub lossless_data_size: c_int,
-    #[cfg(not(feature = "0_5"))]
+    #[cfg(libwebp_1_5)]
+    #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "1_5")))]
+    pub cross_color_transform_bits: c_int,
+    #[cfg(not(libwebp_0_5))]
     #[doc(hidden)]
     pub pad: [u32; 4],
-    #[cfg(feature = "0_5")]
+    #[cfg(all(libwebp_0_5, not(libwebp_1_5)))]
     #[doc(hidden)]
     pub pad: [u32; 2],
+    #[cfg(libwebp_1_5)]
+    #[doc(hidden)]
+    pub pad: [u32; 1],
 }
 
 pub type WebPWriterFunction = Option<extern "C" fn(*const u8, usize, *const WebPPicture) -> c_int>;
@@ -207,7 +215,7 @@ pub struct WebPPicture {
     pub pad7: [*mut c_void; 2],
 }
 
-extern "C" {
+webp_extern! {
     pub fn WebPGetEncoderVersion() -> c_int;
     pub fn WebPEncodeRGB(
         rgb: *const u8,
@@ -270,24 +278,28 @@ extern "C" {
         output: *mut *mut u8,
     ) -> usize;
     #[doc(hidden)]
-    pub fn WebPConfigInitInternal(_: *mut WebPConfig, _: WebPPreset, _: c_float, _: c_int)
-        -> c_int;
-    #[cfg(feature = "0_5")]
+    pub fn WebPConfigInitInternal(
+        config: *mut WebPConfig,
+        preset: WebPPreset,
+        quality: c_float,
+        version: c_int,
+    ) -> c_int;
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "0_5")))]
     pub fn WebPConfigLosslessPreset(config: *mut WebPConfig, level: c_int) -> c_int;
     pub fn WebPValidateConfig(config: *const WebPConfig) -> c_int;
     pub fn WebPMemoryWriterInit(writer: *mut WebPMemoryWriter);
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "0_5")))]
     pub fn WebPMemoryWriterClear(writer: *mut WebPMemoryWriter);
     pub fn WebPMemoryWrite(data: *const u8, data_size: usize, picture: *const WebPPicture)
         -> c_int;
     #[doc(hidden)]
-    pub fn WebPPictureInitInternal(_: *mut WebPPicture, _: c_int) -> c_int;
+    pub fn WebPPictureInitInternal(picture: *mut WebPPicture, version: c_int) -> c_int;
     pub fn WebPPictureAlloc(picture: *mut WebPPicture) -> c_int;
     pub fn WebPPictureFree(picture: *mut WebPPicture);
     pub fn WebPPictureCopy(src: *const WebPPicture, dst: *mut WebPPicture) -> c_int;
-    #[cfg(feature = "0_6")]
+    #[cfg(libwebp_0_6)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "0_6")))]
     pub fn WebPPlaneDistortion(
         src: *const u8,
@@ -360,7 +372,7 @@ extern "C" {
         colorspace: WebPEncCSP,
         dithering: c_float,
     ) -> c_int;
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "0_5")))]
     pub fn WebPPictureSmartARGBToYUVA(picture: *mut WebPPicture) -> c_int;
     pub fn WebPPictureYUVAToARGB(picture: *mut WebPPicture) -> c_int;
@@ -373,11 +385,14 @@ extern "C" {
 #[allow(non_snake_case)]
 #[inline]
 pub unsafe extern "C" fn WebPConfigInit(config: *mut WebPConfig) -> c_int {
-    WebPConfigInitInternal(
-        config,
-        WEBP_PRESET_DEFAULT,
-        75_f32 as c_float,
-        WEBP_ENCODER_ABI_VERSION,
+    webp_call!(
+        0,
+        WebPConfigInitInternal(
+            config,
+            WEBP_PRESET_DEFAULT,
+            75_f32 as c_float,
+            WEBP_ENCODER_ABI_VERSION,
+        )
     )
 }
 
@@ -388,13 +403,19 @@ pub unsafe extern "C" fn WebPConfigPreset(
     preset: WebPPreset,
     quality: c_float,
 ) -> c_int {
-    WebPConfigInitInternal(config, preset, quality, WEBP_ENCODER_ABI_VERSION)
+    webp_call!(
+        0,
+        WebPConfigInitInternal(config, preset, quality, WEBP_ENCODER_ABI_VERSION)
+    )
 }
 
 #[allow(non_snake_case)]
 #[inline]
 pub unsafe extern "C" fn WebPPictureInit(picture: *mut WebPPicture) -> c_int {
-    WebPPictureInitInternal(picture, WEBP_ENCODER_ABI_VERSION)
+    webp_call!(
+        0,
+        WebPPictureInitInternal(picture, WEBP_ENCODER_ABI_VERSION)
+    )
 }
 
 #[cfg(test)]
@@ -408,7 +429,7 @@ mod tests {
         unsafe {
             let mut buf = mem::zeroed();
             WebPMemoryWriterInit(&mut buf);
-            #[cfg(feature = "0_5")]
+            #[cfg(libwebp_0_5)]
             WebPMemoryWriterClear(&mut buf);
         }
     }
@@ -0,0 +1,716 @@
+//! Safe encoding of still images.
+//!
+//! [`Picture`] owns a `WebPPicture` and [`EncoderConfig`] a validated
+//! `WebPConfig`. Encoded output is written directly into a `Vec<u8>`.
+//! [`PictureView`] is a rectangle of a picture that borrows its pixels.
+
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::os::raw::*;
+use std::ptr;
+use std::slice;
+
+use crate::decoder::PixelLayout;
+#[cfg(feature = "dynamic")]
+use crate::dynamic;
+use crate::encode::*;
+use crate::version;
+
+/// Errors of the safe encoders.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncodeError {
+    /// The linked libwebp is incompatible with the bindings.
+    Incompatible(version::Report),
+    /// libwebp could not be loaded.
+    #[cfg(feature = "dynamic")]
+    #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "dynamic")))]
+    Load(dynamic::Error),
+    /// libwebp reported an error (`WebPPicture.error_code`).
+    Encoding(WebPEncodingError),
+    /// The input buffer is smaller than the given dimensions and stride.
+    BufferTooSmall,
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncodeError::Incompatible(report) => report.fmt(f),
+            #[cfg(feature = "dynamic")]
+            EncodeError::Load(e) => e.fmt(f),
+            EncodeError::Encoding(code) => f.write_str(match *code {
+                VP8_ENC_ERROR_OUT_OF_MEMORY => "out of memory",
+                VP8_ENC_ERROR_BITSTREAM_OUT_OF_MEMORY => "out of memory flushing bits",
+                VP8_ENC_ERROR_NULL_PARAMETER => "null parameter",
+                VP8_ENC_ERROR_INVALID_CONFIGURATION => "invalid configuration",
+                VP8_ENC_ERROR_BAD_DIMENSION => "bad picture dimension",
+                VP8_ENC_ERROR_PARTITION0_OVERFLOW => "partition 0 is too big",
+                VP8_ENC_ERROR_PARTITION_OVERFLOW => "partition is too big",
+                VP8_ENC_ERROR_BAD_WRITE => "error writing output",
+                VP8_ENC_ERROR_FILE_TOO_BIG => "file is too big",
+                VP8_ENC_ERROR_USER_ABORT => "encoding aborted",
+                _ => "unknown encoding error",
+            }),
+            EncodeError::BufferTooSmall => f.write_str("input buffer too small"),
+        }
+    }
+}
+
+impl Error for EncodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EncodeError::Incompatible(report) => Some(report),
+            #[cfg(feature = "dynamic")]
+            EncodeError::Load(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<version::Report> for EncodeError {
+    fn from(report: version::Report) -> EncodeError {
+        EncodeError::Incompatible(report)
+    }
+}
+
+#[cfg(feature = "dynamic")]
+impl From<dynamic::Error> for EncodeError {
+    fn from(e: dynamic::Error) -> EncodeError {
+        EncodeError::Load(e)
+    }
+}
+
+/// Encoding parameters.
+#[derive(Clone, Copy)]
+pub struct EncoderConfig {
+    raw: WebPConfig,
+}
+
+impl fmt::Debug for EncoderConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EncoderConfig")
+            .field("lossless", &self.raw.lossless)
+            .field("quality", &self.raw.quality)
+            .field("method", &self.raw.method)
+            .finish()
+    }
+}
+
+impl EncoderConfig {
+    /// Lossy encoding at quality 75.
+    pub fn new() -> Result<EncoderConfig, EncodeError> {
+        EncoderConfig::with_preset(WEBP_PRESET_DEFAULT, 75.0)
+    }
+
+    /// Lossy encoding at the given quality (0-100).
+    pub fn lossy(quality: f32) -> Result<EncoderConfig, EncodeError> {
+        EncoderConfig::with_preset(WEBP_PRESET_DEFAULT, quality)
+    }
+
+    /// Lossless encoding. `quality` (0-100) trades speed for size.
+    pub fn lossless(quality: f32) -> Result<EncoderConfig, EncodeError> {
+        let mut config = EncoderConfig::with_preset(WEBP_PRESET_DEFAULT, quality)?;
+        config.raw.lossless = 1;
+        Ok(config)
+    }
+
+    /// Initializes the parameters from a `WEBP_PRESET_*`.
+    pub fn with_preset(preset: WebPPreset, quality: f32) -> Result<EncoderConfig, EncodeError> {
+        #[cfg(feature = "dynamic")]
+        dynamic::load()?;
+        version::ensure()?;
+        let mut raw: WebPConfig = unsafe { mem::zeroed() };
+        if unsafe { WebPConfigPreset(&mut raw, preset, quality) } == 0 {
+            return Err(EncodeError::Encoding(VP8_ENC_ERROR_INVALID_CONFIGURATION));
+        }
+        Ok(EncoderConfig { raw })
+    }
+
+    pub fn set_quality(&mut self, quality: f32) -> &mut Self {
+        self.raw.quality = quality;
+        self
+    }
+
+    pub fn set_lossless(&mut self, lossless: bool) -> &mut Self {
+        self.raw.lossless = lossless as c_int;
+        self
+    }
+
+    /// Quality/speed trade-off, 0 (fast) to 6 (slower, better).
+    pub fn set_method(&mut self, method: c_int) -> &mut Self {
+        self.raw.method = method;
+        self
+    }
+
+    pub fn raw(&self) -> &WebPConfig {
+        &self.raw
+    }
+
+    /// The underlying `WebPConfig`, for parameters without a setter.
+    pub fn raw_mut(&mut self) -> &mut WebPConfig {
+        &mut self.raw
+    }
+
+    /// Checks the parameters with `WebPValidateConfig`.
+    pub fn validate(&self) -> Result<(), EncodeError> {
+        if unsafe { WebPValidateConfig(&self.raw) } == 0 {
+            return Err(EncodeError::Encoding(VP8_ENC_ERROR_INVALID_CONFIGURATION));
+        }
+        Ok(())
+    }
+}
+
+/// An owned `WebPPicture`.
+pub struct Picture {
+    raw: WebPPicture,
+}
+
+unsafe impl Send for Picture {}
+
+impl fmt::Debug for Picture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Picture")
+            .field("width", &self.raw.width)
+            .field("height", &self.raw.height)
+            .field("use_argb", &self.raw.use_argb)
+            .finish()
+    }
+}
+
+impl Picture {
+    /// An empty picture without pixel buffers.
+    pub(crate) fn empty() -> Result<Picture, EncodeError> {
+        #[cfg(feature = "dynamic")]
+        dynamic::load()?;
+        version::ensure()?;
+        let mut raw: WebPPicture = unsafe { mem::zeroed() };
+        if unsafe { WebPPictureInit(&mut raw) } == 0 {
+            return Err(EncodeError::Encoding(VP8_ENC_ERROR_INVALID_CONFIGURATION));
+        }
+        Ok(Picture { raw })
+    }
+
+    /// Allocates a transparent black ARGB picture.
+    pub fn new(width: u32, height: u32) -> Result<Picture, EncodeError> {
+        let mut picture = Picture::empty()?;
+        picture.set_dimensions(width, height)?;
+        picture.raw.use_argb = 1;
+        if unsafe { WebPPictureAlloc(&mut picture.raw) } == 0 {
+            return Err(picture.error());
+        }
+        // WebPPictureAlloc leaves the pixels uninitialized.
+        let raw = &picture.raw;
+        unsafe { ptr::write_bytes(raw.argb, 0, raw.argb_stride as usize * raw.height as usize) };
+        Ok(picture)
+    }
+
+    /// Imports packed pixels in the given layout, rows `stride` bytes apart,
+    /// into an ARGB picture.
+    pub fn import(
+        layout: PixelLayout,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        stride: usize,
+    ) -> Result<Picture, EncodeError> {
+        let import = match layout {
+            PixelLayout::Rgb => WebPPictureImportRGB,
+            PixelLayout::Rgba => WebPPictureImportRGBA,
+            PixelLayout::Bgr => WebPPictureImportBGR,
+            PixelLayout::Bgra => WebPPictureImportBGRA,
+            PixelLayout::Argb => return Err(EncodeError::Encoding(VP8_ENC_ERROR_NULL_PARAMETER)),
+        };
+        let mut picture = Picture::empty()?;
+        picture.set_dimensions(width, height)?;
+        if stride > c_int::MAX as usize {
+            return Err(EncodeError::Encoding(VP8_ENC_ERROR_BAD_DIMENSION));
+        }
+        let row = width as usize * layout.bytes_per_pixel();
+        if height > 0 {
+            let fits = stride
+                .checked_mul(height as usize - 1)
+                .and_then(|size| size.checked_add(row))
+                .map(|needed| data.len() >= needed);
+            if stride < row || fits != Some(true) {
+                return Err(EncodeError::BufferTooSmall);
+            }
+        }
+        picture.raw.use_argb = 1;
+        if unsafe { import(&mut picture.raw, data.as_ptr(), stride as c_int) } == 0 {
+            return Err(picture.error());
+        }
+        Ok(picture)
+    }
+
+    /// Imports tightly packed RGBA pixels.
+    pub fn from_rgba(data: &[u8], width: u32, height: u32) -> Result<Picture, EncodeError> {
+        Picture::import(PixelLayout::Rgba, data, width, height, width as usize * 4)
+    }
+
+    /// Imports tightly packed RGB pixels.
+    pub fn from_rgb(data: &[u8], width: u32, height: u32) -> Result<Picture, EncodeError> {
+        Picture::import(PixelLayout::Rgb, data, width, height, width as usize * 3)
+    }
+
+    fn set_dimensions(&mut self, width: u32, height: u32) -> Result<(), EncodeError> {
+        if width > WEBP_MAX_DIMENSION as u32 || height > WEBP_MAX_DIMENSION as u32 {
+            return Err(EncodeError::Encoding(VP8_ENC_ERROR_BAD_DIMENSION));
+        }
+        self.raw.width = width as c_int;
+        self.raw.height = height as c_int;
+        Ok(())
+    }
+
+    pub(crate) fn error(&self) -> EncodeError {
+        EncodeError::Encoding(self.raw.error_code)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.raw.width as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.raw.height as u32
+    }
+
+    pub fn as_raw(&self) -> &WebPPicture {
+        &self.raw
+    }
+
+    /// The underlying `WebPPicture`. Its buffers must stay owned by the
+    /// picture, as they are released with `WebPPictureFree` on drop.
+    pub fn as_mut_ptr(&mut self) -> *mut WebPPicture {
+        &mut self.raw
+    }
+
+    /// Copies the picture and its pixels with `WebPPictureCopy`.
+    pub fn try_clone(&self) -> Result<Picture, EncodeError> {
+        let mut copy = Picture::empty()?;
+        if unsafe { WebPPictureCopy(&self.raw, &mut copy.raw) } == 0 {
+            return Err(EncodeError::Encoding(VP8_ENC_ERROR_OUT_OF_MEMORY));
+        }
+        Ok(copy)
+    }
+
+    /// Encodes the picture. It may be converted to the colorspace required by
+    /// `config` in place.
+    pub fn encode(&mut self, config: &EncoderConfig) -> Result<Vec<u8>, EncodeError> {
+        let mut output = Vec::new();
+        self.encode_into(config, &mut output)?;
+        Ok(output)
+    }
+
+    /// Like [`Picture::encode`], but appends to `output`.
+    pub fn encode_into(
+        &mut self,
+        config: &EncoderConfig,
+        output: &mut Vec<u8>,
+    ) -> Result<(), EncodeError> {
+        self.encode_raw(config, output, ptr::null_mut())
+    }
+
+    /// Encodes into `output`, filling `stats` if it is not null.
+    pub(crate) fn encode_raw(
+        &mut self,
+        config: &EncoderConfig,
+        output: &mut Vec<u8>,
+        stats: *mut WebPAuxStats,
+    ) -> Result<(), EncodeError> {
+        encode_raw(&mut self.raw, config, output, stats)
+    }
+
+    /// Whether the picture is a view (`WebPPictureIsView`). Owned pictures
+    /// never are.
+    pub fn is_view(&self) -> bool {
+        unsafe { WebPPictureIsView(&self.raw) != 0 }
+    }
+
+    /// Whether any pixel is not fully opaque.
+    pub fn has_transparency(&self) -> bool {
+        unsafe { WebPPictureHasTransparency(&self.raw) != 0 }
+    }
+
+    /// Crops the picture to the rectangle in place, reallocating its
+    /// buffers. For YUV pictures `left` and `top` are snapped to even values.
+    pub fn crop(
+        &mut self,
+        left: u32,
+        top: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<(), EncodeError> {
+        check_rect(&self.raw, left, top, width, height)?;
+        let ok = unsafe {
+            WebPPictureCrop(
+                &mut self.raw,
+                left as c_int,
+                top as c_int,
+                width as c_int,
+                height as c_int,
+            )
+        };
+        if ok == 0 {
+            return Err(self.error());
+        }
+        Ok(())
+    }
+
+    /// A view of the rectangle sharing the pixels of the picture. For YUV
+    /// pictures `left` and `top` are snapped to even values.
+    ///
+    /// The view borrows the picture mutably: encoding can write to the
+    /// shared pixels (see [`PictureView::encode`]), and the picture must
+    /// outlive the view.
+    pub fn view(
+        &mut self,
+        left: u32,
+        top: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<PictureView<'_>, EncodeError> {
+        check_rect(&self.raw, left, top, width, height)?;
+        let mut raw: WebPPicture = unsafe { mem::zeroed() };
+        let ok = unsafe {
+            WebPPictureView(
+                &self.raw,
+                left as c_int,
+                top as c_int,
+                width as c_int,
+                height as c_int,
+                &mut raw,
+            )
+        };
+        if ok == 0 {
+            return Err(EncodeError::Encoding(VP8_ENC_ERROR_BAD_DIMENSION));
+        }
+        Ok(PictureView {
+            raw,
+            parent: PhantomData,
+        })
+    }
+
+    /// Rescales the picture in place. Recent libwebp versions compute a
+    /// `width` or `height` of 0 from the aspect ratio.
+    pub fn rescale(&mut self, width: u32, height: u32) -> Result<(), EncodeError> {
+        if width > WEBP_MAX_DIMENSION as u32 || height > WEBP_MAX_DIMENSION as u32 {
+            return Err(EncodeError::Encoding(VP8_ENC_ERROR_BAD_DIMENSION));
+        }
+        if unsafe { WebPPictureRescale(&mut self.raw, width as c_int, height as c_int) } == 0 {
+            return Err(match self.raw.error_code {
+                VP8_ENC_OK => EncodeError::Encoding(VP8_ENC_ERROR_BAD_DIMENSION),
+                code => EncodeError::Encoding(code),
+            });
+        }
+        Ok(())
+    }
+
+    /// Blends the picture onto the opaque `0xRRGGBB` background and drops
+    /// the alpha channel (`WebPBlendAlpha`).
+    pub fn blend_alpha(&mut self, background_rgb: u32) {
+        unsafe { WebPBlendAlpha(&mut self.raw, background_rgb) }
+    }
+
+    /// Smooths the color of fully transparent areas to help compression
+    /// (`WebPCleanupTransparentArea`).
+    pub fn cleanup_transparent_area(&mut self) {
+        unsafe { WebPCleanupTransparentArea(&mut self.raw) }
+    }
+}
+
+impl Drop for Picture {
+    fn drop(&mut self) {
+        unsafe { WebPPictureFree(&mut self.raw) }
+    }
+}
+
+/// A rectangle of a [`Picture`] sharing its pixels (`WebPPictureView`).
+///
+/// Encoding may allocate buffers of the view's own, for example when
+/// converting ARGB to YUV; they are released on drop while the parent's
+/// pixels are left alone.
+pub struct PictureView<'a> {
+    raw: WebPPicture,
+    parent: PhantomData<&'a mut Picture>,
+}
+
+unsafe impl Send for PictureView<'_> {}
+
+impl fmt::Debug for PictureView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PictureView")
+            .field("width", &self.raw.width)
+            .field("height", &self.raw.height)
+            .field("use_argb", &self.raw.use_argb)
+            .finish()
+    }
+}
+
+impl PictureView<'_> {
+    pub fn width(&self) -> u32 {
+        self.raw.width as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.raw.height as u32
+    }
+
+    pub fn as_raw(&self) -> &WebPPicture {
+        &self.raw
+    }
+
+    /// Whether the view still shares the parent's pixels. It stops doing so
+    /// once encoding has converted it to another colorspace.
+    pub fn is_view(&self) -> bool {
+        unsafe { WebPPictureIsView(&self.raw) != 0 }
+    }
+
+    /// Copies the pixels of the view into an owned picture.
+    pub fn to_picture(&self) -> Result<Picture, EncodeError> {
+        let mut copy = Picture::empty()?;
+        if unsafe { WebPPictureCopy(&self.raw, &mut copy.raw) } == 0 {
+            return Err(EncodeError::Encoding(VP8_ENC_ERROR_OUT_OF_MEMORY));
+        }
+        Ok(copy)
+    }
+
+    /// Encodes the view. Unless `exact` is set, libwebp may modify the
+    /// shared pixels of transparent areas in the parent.
+    pub fn encode(&mut self, config: &EncoderConfig) -> Result<Vec<u8>, EncodeError> {
+        let mut output = Vec::new();
+        self.encode_into(config, &mut output)?;
+        Ok(output)
+    }
+
+    /// Like [`PictureView::encode`], but appends to `output`.
+    pub fn encode_into(
+        &mut self,
+        config: &EncoderConfig,
+        output: &mut Vec<u8>,
+    ) -> Result<(), EncodeError> {
+        encode_raw(&mut self.raw, config, output, ptr::null_mut())
+    }
+
+    /// Like [`Picture::blend_alpha`], on the shared pixels.
+    pub fn blend_alpha(&mut self, background_rgb: u32) {
+        unsafe { WebPBlendAlpha(&mut self.raw, background_rgb) }
+    }
+
+    /// Like [`Picture::cleanup_transparent_area`], on the shared pixels.
+    pub fn cleanup_transparent_area(&mut self) {
+        unsafe { WebPCleanupTransparentArea(&mut self.raw) }
+    }
+}
+
+impl Drop for PictureView<'_> {
+    fn drop(&mut self) {
+        // Only frees buffers allocated for the view itself.
+        unsafe { WebPPictureFree(&mut self.raw) }
+    }
+}
+
+/// Checks that the rectangle lies within the picture.
+fn check_rect(
+    raw: &WebPPicture,
+    left: u32,
+    top: u32,
+    width: u32,
+    height: u32,
+) -> Result<(), EncodeError> {
+    let fits = |start: u32, len: u32, max: c_int| {
+        len > 0 && u64::from(start) + u64::from(len) <= max as u64
+    };
+    if !fits(left, width, raw.width) || !fits(top, height, raw.height) {
+        return Err(EncodeError::Encoding(VP8_ENC_ERROR_BAD_DIMENSION));
+    }
+    Ok(())
+}
+
+fn encode_raw(
+    raw: &mut WebPPicture,
+    config: &EncoderConfig,
+    output: &mut Vec<u8>,
+    stats: *mut WebPAuxStats,
+) -> Result<(), EncodeError> {
+    config.validate()?;
+    let saved = (raw.writer, raw.custom_ptr, raw.stats);
+    raw.writer = Some(write_vec);
+    raw.custom_ptr = output as *mut Vec<u8> as *mut c_void;
+    raw.stats = stats;
+    let ok = unsafe { WebPEncode(&config.raw, raw) };
+    raw.writer = saved.0;
+    raw.custom_ptr = saved.1;
+    raw.stats = saved.2;
+    if ok == 0 {
+        return Err(EncodeError::Encoding(raw.error_code));
+    }
+    Ok(())
+}
+
+extern "C" fn write_vec(data: *const u8, data_size: usize, picture: *const WebPPicture) -> c_int {
+    unsafe {
+        let output = &mut *((*picture).custom_ptr as *mut Vec<u8>);
+        if data_size > 0 {
+            output.extend_from_slice(slice::from_raw_parts(data, data_size));
+        }
+    }
+    1
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::decoder::{Decoder, Format};
+
+    /// RGBA pixels with red increasing to the right and green downwards.
+    pub(crate) fn gradient(width: u32, height: u32, alpha: u8) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                vec![
+                    (x * 256 / width) as u8,
+                    (y * 256 / height) as u8,
+                    128,
+                    alpha,
+                ]
+            })
+            .collect()
+    }
+
+    /// Pseudo-random bytes from a linear congruential generator.
+    pub(crate) fn noise(seed: u32, len: usize) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_encode_lossless() {
+        let rgba = gradient(32, 16, 255);
+        let mut picture = Picture::from_rgba(&rgba, 32, 16).unwrap();
+        let webp = picture
+            .encode(&EncoderConfig::lossless(75.0).unwrap())
+            .unwrap();
+        let decoder = Decoder::new(&webp).unwrap();
+        assert_eq!(decoder.features().format, Format::Lossless);
+        assert_eq!(decoder.decode(PixelLayout::Rgba).unwrap().data, rgba);
+    }
+
+    #[test]
+    fn test_encode_lossy() {
+        let rgba = gradient(32, 16, 255);
+        let mut picture = Picture::from_rgba(&rgba, 32, 16).unwrap();
+        let low = picture
+            .encode(&EncoderConfig::lossy(10.0).unwrap())
+            .unwrap();
+        let high = picture
+            .encode(&EncoderConfig::lossy(95.0).unwrap())
+            .unwrap();
+        assert_eq!(Decoder::new(&low).unwrap().features().format, Format::Lossy);
+        assert!(low.len() < high.len());
+    }
+
+    #[test]
+    fn test_new_is_transparent() {
+        let picture = Picture::new(5, 3).unwrap();
+        let raw = &picture.raw;
+        let argb = unsafe { slice::from_raw_parts(raw.argb, raw.argb_stride as usize * 3) };
+        assert!(argb.iter().all(|&pixel| pixel == 0));
+    }
+
+    #[test]
+    fn test_crop_and_view() {
+        let rgba = gradient(32, 16, 255);
+        let mut picture = Picture::from_rgba(&rgba, 32, 16).unwrap();
+        let config = EncoderConfig::lossless(50.0).unwrap();
+        let expected: Vec<u8> = (4..12)
+            .flat_map(|y| rgba[(y * 32 + 8) * 4..][..10 * 4].to_vec())
+            .collect();
+
+        let mut view = picture.view(8, 4, 10, 8).unwrap();
+        assert!(view.is_view());
+        assert_eq!((view.width(), view.height()), (10, 8));
+        let webp = view.encode(&config).unwrap();
+        let copy = view.to_picture().unwrap();
+        drop(view);
+        let decoded = Decoder::new(&webp)
+            .unwrap()
+            .decode(PixelLayout::Rgba)
+            .unwrap();
+        assert_eq!(decoded.data, expected);
+        assert!(!copy.is_view());
+
+        assert!(!picture.is_view());
+        assert_eq!(
+            picture.view(30, 0, 4, 4).unwrap_err(),
+            EncodeError::Encoding(VP8_ENC_ERROR_BAD_DIMENSION)
+        );
+        picture.crop(8, 4, 10, 8).unwrap();
+        assert_eq!((picture.width(), picture.height()), (10, 8));
+        let webp = picture.encode(&config).unwrap();
+        let decoded = Decoder::new(&webp)
+            .unwrap()
+            .decode(PixelLayout::Rgba)
+            .unwrap();
+        assert_eq!(decoded.data, expected);
+        assert!(picture.crop(0, 0, 0, 1).is_err());
+    }
+
+    #[test]
+    fn test_rescale() {
+        let mut picture = Picture::from_rgba(&gradient(32, 16, 255), 32, 16).unwrap();
+        picture.rescale(16, 8).unwrap();
+        assert_eq!((picture.width(), picture.height()), (16, 8));
+        assert!(picture.rescale(0, 0).is_err());
+    }
+
+    #[test]
+    fn test_alpha() {
+        let mut rgba = gradient(16, 16, 255);
+        for pixel in rgba.chunks_exact_mut(4).take(16 * 8) {
+            pixel[3] = 0;
+        }
+        let mut picture = Picture::from_rgba(&rgba, 16, 16).unwrap();
+        assert!(picture.has_transparency());
+        let mut view = picture.view(0, 8, 16, 8).unwrap();
+        view.blend_alpha(0xff_00_00);
+        drop(view);
+        assert!(picture.has_transparency());
+        picture.cleanup_transparent_area();
+        picture.blend_alpha(0x00_00_ff);
+        assert!(!picture.has_transparency());
+        let webp = picture
+            .encode(&EncoderConfig::lossless(50.0).unwrap())
+            .unwrap();
+        let decoded = Decoder::new(&webp)
+            .unwrap()
+            .decode(PixelLayout::Rgba)
+            .unwrap();
+        assert_eq!(&decoded.data[..4], &[0, 0, 0xff, 0xff]);
+        assert_eq!(&decoded.data[16 * 8 * 4..][..4], &rgba[16 * 8 * 4..][..4]);
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert_eq!(
+            Picture::from_rgb(&[0; 10], 2, 2).unwrap_err(),
+            EncodeError::BufferTooSmall
+        );
+        assert_eq!(
+            Picture::import(PixelLayout::Rgba, &[0; 16], 1, 3, c_int::MAX as usize).unwrap_err(),
+            EncodeError::BufferTooSmall
+        );
+        let mut config = EncoderConfig::new().unwrap();
+        config.set_quality(200.0);
+        assert!(config.validate().is_err());
+        let mut picture = Picture::new(4, 4).unwrap();
+        assert_eq!(
+            picture.encode(&config).unwrap_err(),
+            EncodeError::Encoding(VP8_ENC_ERROR_INVALID_CONFIGURATION)
+        );
+    }
+}
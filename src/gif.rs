@@ -0,0 +1,459 @@
+//! GIF conversion (the `gif` feature, with `mux`).
+//!
+//! [`gif_to_webp`] works like libwebp's `gif2webp`: every GIF frame is
+//! composed onto a full canvas, honoring its transparent index and disposal
+//! method, and the canvases are given to [`AnimEncoder`], which chooses the
+//! `WebPMuxAnimDispose` and `WebPMuxAnimBlend` of each WebP frame.
+//!
+//! [`webp_to_gif`] goes the other way (with `demux`): every canvas rendered
+//! by [`AnimDecoder`] becomes a full GIF frame with its own palette of at
+//! most 256 colors.
+
+use std::borrow::Cow;
+#[cfg(feature = "demux")]
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+#[cfg(feature = "demux")]
+use std::io::Write;
+use std::time::Duration;
+
+use ::gif::{
+    ColorOutput, DecodeOptions, DecodingError, DisposalMethod, EncodingError, Frame, Repeat,
+};
+
+#[cfg(feature = "demux")]
+use crate::anim::AnimDecoder;
+use crate::anim_encoder::{AnimEncodeError, AnimEncoder, AnimEncoderOptions};
+use crate::canvas::{Canvas, Rect};
+use crate::decoder::DecodeError;
+use crate::encoder::EncoderConfig;
+
+/// Errors of the GIF conversions.
+#[derive(Debug)]
+pub enum GifError {
+    Decode(DecodingError),
+    Encode(AnimEncodeError),
+    /// Decoding the WebP failed.
+    WebpDecode(DecodeError),
+    /// Writing the GIF failed.
+    GifEncode(EncodingError),
+    /// The WebP canvas, with this width and height, exceeds the 65535×65535
+    /// pixels of GIF.
+    TooLarge(u32, u32),
+}
+
+impl fmt::Display for GifError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GifError::Decode(e) => e.fmt(f),
+            GifError::Encode(e) => e.fmt(f),
+            GifError::WebpDecode(e) => e.fmt(f),
+            GifError::GifEncode(e) => e.fmt(f),
+            GifError::TooLarge(width, height) => {
+                write!(f, "a {}x{} canvas is too large for GIF", width, height)
+            }
+        }
+    }
+}
+
+impl Error for GifError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GifError::Decode(e) => Some(e),
+            GifError::Encode(e) => Some(e),
+            GifError::WebpDecode(e) => Some(e),
+            GifError::GifEncode(e) => Some(e),
+            GifError::TooLarge(..) => None,
+        }
+    }
+}
+
+impl From<DecodingError> for GifError {
+    fn from(e: DecodingError) -> GifError {
+        GifError::Decode(e)
+    }
+}
+
+impl From<AnimEncodeError> for GifError {
+    fn from(e: AnimEncodeError) -> GifError {
+        GifError::Encode(e)
+    }
+}
+
+impl From<DecodeError> for GifError {
+    fn from(e: DecodeError) -> GifError {
+        GifError::WebpDecode(e)
+    }
+}
+
+impl From<EncodingError> for GifError {
+    fn from(e: EncodingError) -> GifError {
+        GifError::GifEncode(e)
+    }
+}
+
+/// Background of a GIF whose background index is missing or invalid.
+const WHITE: u32 = 0xffff_ffff;
+
+/// Converts a GIF to an animated WebP.
+///
+/// Each frame is encoded with `config`: lossless or lossy depending on
+/// `config.lossless`, or either with [`AnimEncoderOptions::set_allow_mixed`].
+/// The loop count and background color of `options` are replaced by those
+/// of the GIF. As in `gif2webp`, frame delays of 10 ms or less become 100 ms.
+pub fn gif_to_webp<R: Read>(
+    reader: R,
+    options: &AnimEncoderOptions,
+    config: &EncoderConfig,
+) -> Result<Vec<u8>, GifError> {
+    let mut decode_options = DecodeOptions::new();
+    decode_options.set_color_output(ColorOutput::Indexed);
+    let mut decoder = decode_options.read_info(reader)?;
+    let (width, height) = (u32::from(decoder.width()), u32::from(decoder.height()));
+    let global_palette = decoder.global_palette().map(|p| p.to_vec());
+    let bg_index = decoder.bg_color();
+
+    let mut canvas = Canvas::new(width, height);
+    let mut encoder = None;
+    let mut timestamp = Duration::from_millis(0);
+    let mut buffer = Vec::new();
+    while let Some(info) = decoder.next_frame_info()? {
+        let mut frame: Frame<'_> = info.clone();
+        if encoder.is_none() {
+            let mut options = *options;
+            options.set_bgcolor(background(
+                global_palette.as_deref(),
+                bg_index,
+                frame.transparent,
+            ));
+            // The loop extension precedes the first image, so it is known
+            // by now.
+            options.set_loop_count(loop_count(decoder.repeat()));
+            encoder = Some(AnimEncoder::new(width, height, &options)?);
+        }
+        buffer.resize(decoder.buffer_size(), 0);
+        decoder.read_into_buffer(&mut buffer)?;
+        frame.buffer = Cow::Borrowed(&buffer);
+        let frame = &frame;
+        let encoder = encoder.as_mut().unwrap();
+        let palette = frame
+            .palette
+            .as_deref()
+            .or(global_palette.as_deref())
+            .unwrap_or(&[]);
+        let previous = match frame.dispose {
+            DisposalMethod::Previous => Some(canvas.pixels.clone()),
+            _ => None,
+        };
+        let rect = Rect {
+            left: u32::from(frame.left),
+            top: u32::from(frame.top),
+            width: u32::from(frame.width),
+            height: u32::from(frame.height),
+        };
+        // Transparent pixels and indices outside the palette leave the
+        // canvas as it is.
+        canvas.draw(rect, |dst, x, y| {
+            let index = frame.buffer[y * usize::from(frame.width) + x];
+            if Some(index) == frame.transparent {
+                return;
+            }
+            let i = usize::from(index) * 3;
+            if let Some(rgb) = palette.get(i..i + 3) {
+                dst.copy_from_slice(&[rgb[0], rgb[1], rgb[2], 0xff]);
+            }
+        });
+
+        let mut picture = canvas.to_picture()?;
+        encoder.add_frame(&mut picture, timestamp, config)?;
+
+        match frame.dispose {
+            DisposalMethod::Background => canvas.clear(rect),
+            DisposalMethod::Previous => canvas.pixels = previous.unwrap(),
+            DisposalMethod::Any | DisposalMethod::Keep => {}
+        }
+        let delay = u64::from(frame.delay) * 10;
+        timestamp += Duration::from_millis(if delay <= 10 { 100 } else { delay });
+    }
+    let encoder = match encoder {
+        Some(encoder) => encoder,
+        None => {
+            // No frames: encode the empty canvas as a still image.
+            let mut encoder = AnimEncoder::new(width, height, options)?;
+            let mut picture = canvas.to_picture()?;
+            encoder.add_frame(&mut picture, timestamp, config)?;
+            encoder
+        }
+    };
+    Ok(encoder.finish(timestamp)?)
+}
+
+/// The WebP loop count of a GIF. GIF counts the repetitions after the first
+/// play, WebP all plays, and a GIF without a loop extension plays once.
+fn loop_count(repeat: Repeat) -> u32 {
+    match repeat {
+        Repeat::Infinite => 0,
+        Repeat::Finite(n) if n < 65535 => u32::from(n) + 1,
+        Repeat::Finite(n) => u32::from(n),
+    }
+}
+
+/// Converts an animated or still WebP to a GIF written to `writer`.
+///
+/// Pixels with an alpha below `alpha_threshold` become transparent and the
+/// others opaque. Frames with more than 256 colors are quantized with
+/// NeuQuant. Delays are rounded to hundredths of a second without
+/// accumulating the rounding error, and the loop count is kept.
+#[cfg(feature = "demux")]
+#[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "demux")))]
+pub fn webp_to_gif<W: Write>(data: &[u8], writer: W, alpha_threshold: u8) -> Result<(), GifError> {
+    let mut decoder = AnimDecoder::new(data)?;
+    let info = decoder.info();
+    // A WebP canvas may be up to 2^24 pixels wide and high, a GIF only 65535.
+    let (width, height) = match (
+        u16::try_from(info.canvas_width),
+        u16::try_from(info.canvas_height),
+    ) {
+        (Ok(width), Ok(height)) => (width, height),
+        _ => return Err(GifError::TooLarge(info.canvas_width, info.canvas_height)),
+    };
+    let mut encoder = ::gif::Encoder::new(writer, width, height, &[])?;
+    match info.loop_count {
+        _ if info.frame_count <= 1 => {}
+        0 => encoder.set_repeat(Repeat::Infinite)?,
+        // Without a loop extension a GIF plays once.
+        1 => {}
+        n => encoder.set_repeat(Repeat::Finite((n - 1).min(65535) as u16))?,
+    }
+
+    let mut start = 0;
+    while let Some(frame) = decoder.next_frame()? {
+        let mut pixels = frame.data.to_vec();
+        for pixel in pixels.chunks_exact_mut(4) {
+            if pixel[3] < alpha_threshold {
+                pixel.copy_from_slice(&[0, 0, 0, 0]);
+            } else {
+                pixel[3] = 255;
+            }
+        }
+        let mut gif_frame = Frame::from_rgba_speed(width, height, &mut pixels, 10);
+        let end = ((frame.timestamp.as_millis() + 5) / 10) as u64;
+        gif_frame.delay = (end - start).min(u64::from(u16::MAX)) as u16;
+        // Every frame covers the canvas, so nothing of the previous one
+        // may show through its transparent pixels.
+        gif_frame.dispose = DisposalMethod::Background;
+        encoder.write_frame(&gif_frame)?;
+        start = end;
+    }
+    Ok(())
+}
+
+/// The background color like `gif2webp`: transparent if the background
+/// index is the transparent one, white if it is invalid.
+fn background(palette: Option<&[u8]>, bg_index: Option<usize>, transparent: Option<u8>) -> u32 {
+    let index = match bg_index {
+        Some(index) => index,
+        None => return WHITE,
+    };
+    if transparent.map(usize::from) == Some(index) {
+        return 0;
+    }
+    match palette.and_then(|p| p.get(index * 3..index * 3 + 3)) {
+        Some(rgb) => u32::from_be_bytes([0xff, rgb[0], rgb[1], rgb[2]]),
+        None => WHITE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::gif::Encoder;
+
+    #[cfg(feature = "demux")]
+    use crate::anim::AnimDecoder;
+    #[cfg(feature = "demux")]
+    use crate::encoder::Picture;
+    #[cfg(feature = "demux")]
+    use crate::mux::*;
+    #[cfg(feature = "demux")]
+    use crate::mux_types::*;
+
+    const PALETTE: [u8; 9] = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+
+    /// A 4×4 GIF: a red background, then a green 2×2 square at (2, 2)
+    /// disposed to background, then a blue pixel over a transparent frame.
+    fn sample(repeat: Option<Repeat>) -> Vec<u8> {
+        let mut data = Vec::new();
+        {
+            let mut encoder = Encoder::new(&mut data, 4, 4, &PALETTE).unwrap();
+            if let Some(repeat) = repeat {
+                encoder.set_repeat(repeat).unwrap();
+            }
+            let mut first = Frame::from_indexed_pixels(4, 4, vec![0; 16], None);
+            first.delay = 1;
+            encoder.write_frame(&first).unwrap();
+            let mut second = Frame::from_indexed_pixels(2, 2, vec![1; 4], None);
+            second.left = 2;
+            second.top = 2;
+            second.delay = 20;
+            second.dispose = DisposalMethod::Background;
+            encoder.write_frame(&second).unwrap();
+            let mut pixels = vec![1; 16];
+            pixels[0] = 2;
+            let mut third = Frame::from_indexed_pixels(4, 4, pixels, Some(1));
+            third.delay = 30;
+            encoder.write_frame(&third).unwrap();
+        }
+        data
+    }
+
+    fn config() -> (AnimEncoderOptions, EncoderConfig) {
+        (
+            AnimEncoderOptions::new().unwrap(),
+            EncoderConfig::lossless(50.0).unwrap(),
+        )
+    }
+
+    #[cfg(feature = "demux")]
+    #[test]
+    fn test_gif_to_webp() {
+        let (options, config) = config();
+        let webp = gif_to_webp(&sample(Some(Repeat::Finite(2)))[..], &options, &config).unwrap();
+        let mut decoder = AnimDecoder::new(&webp).unwrap();
+        let info = decoder.info();
+        assert_eq!((info.canvas_width, info.canvas_height), (4, 4));
+        assert_eq!(info.frame_count, 3);
+        assert_eq!(info.loop_count, 3);
+        assert_eq!(info.bgcolor, 0xffff_0000);
+        assert_eq!(info.duration, Duration::from_millis(100 + 200 + 300));
+
+        let pixel = |data: &[u8], x: usize, y: usize| data[(y * 4 + x) * 4..][..4].to_vec();
+        let first = decoder.next_frame().unwrap().unwrap();
+        assert_eq!(first.timestamp, Duration::from_millis(100));
+        assert_eq!(pixel(first.data, 3, 3), [255, 0, 0, 255]);
+        let second = decoder.next_frame().unwrap().unwrap();
+        assert_eq!(pixel(second.data, 3, 3), [0, 255, 0, 255]);
+        assert_eq!(pixel(second.data, 0, 0), [255, 0, 0, 255]);
+        let third = decoder.next_frame().unwrap().unwrap();
+        assert_eq!(pixel(third.data, 0, 0), [0, 0, 255, 255]);
+        assert_eq!(pixel(third.data, 1, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(third.data, 3, 3)[3], 0);
+    }
+
+    #[cfg(feature = "demux")]
+    #[test]
+    fn test_loop_count() {
+        let (options, config) = config();
+        let loop_count = |repeat| {
+            let webp = gif_to_webp(&sample(repeat)[..], &options, &config).unwrap();
+            let info = AnimDecoder::new(&webp).unwrap().info();
+            info.loop_count
+        };
+        assert_eq!(loop_count(None), 1);
+        assert_eq!(loop_count(Some(Repeat::Infinite)), 0);
+    }
+
+    #[cfg(feature = "demux")]
+    #[test]
+    fn test_webp_to_gif() {
+        let (options, config) = config();
+        let webp = gif_to_webp(&sample(Some(Repeat::Finite(2)))[..], &options, &config).unwrap();
+        let mut gif = Vec::new();
+        webp_to_gif(&webp, &mut gif, 128).unwrap();
+
+        let mut decode_options = DecodeOptions::new();
+        decode_options.set_color_output(ColorOutput::RGBA);
+        let mut decoder = decode_options.read_info(&gif[..]).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!((frame.width, frame.height), (4, 4));
+            frames.push((frame.delay, frame.buffer.to_vec()));
+        }
+        assert_eq!(decoder.repeat(), Repeat::Finite(2));
+        let delays: Vec<_> = frames.iter().map(|f| f.0).collect();
+        assert_eq!(delays, [10, 20, 30]);
+        let pixel = |data: &[u8], x: usize, y: usize| data[(y * 4 + x) * 4..][..4].to_vec();
+        assert_eq!(pixel(&frames[1].1, 3, 3), [0, 255, 0, 255]);
+        assert_eq!(pixel(&frames[2].1, 0, 0), [0, 0, 255, 255]);
+        assert_eq!(pixel(&frames[2].1, 3, 3)[3], 0);
+    }
+
+    #[cfg(feature = "demux")]
+    #[test]
+    fn test_alpha_threshold() {
+        let rgba = [9, 9, 9, 255, 9, 9, 9, 200, 9, 9, 9, 100, 9, 9, 9, 0];
+        let webp = Picture::from_rgba(&rgba, 2, 2)
+            .unwrap()
+            .encode(&EncoderConfig::lossless(50.0).unwrap())
+            .unwrap();
+        let mut gif = Vec::new();
+        webp_to_gif(&webp, &mut gif, 128).unwrap();
+
+        let mut decode_options = DecodeOptions::new();
+        decode_options.set_color_output(ColorOutput::RGBA);
+        let mut decoder = decode_options.read_info(&gif[..]).unwrap();
+        let frame = decoder.read_next_frame().unwrap().unwrap();
+        let alpha: Vec<_> = frame.buffer.chunks(4).map(|p| p[3]).collect();
+        assert_eq!(alpha, [255, 255, 0, 0]);
+        assert!(decoder.read_next_frame().unwrap().is_none());
+        assert_eq!(decoder.repeat(), Repeat::Finite(0));
+    }
+
+    #[cfg(feature = "demux")]
+    #[test]
+    fn test_too_large() {
+        // One frame on a canvas wider than a GIF can be.
+        let frame = Picture::from_rgba(&[0, 0, 0, 255], 1, 1)
+            .unwrap()
+            .encode(&EncoderConfig::lossless(50.0).unwrap())
+            .unwrap();
+        let webp = unsafe {
+            let mux = WebPMuxNew();
+            let info = WebPMuxFrameInfo {
+                bitstream: WebPData {
+                    bytes: frame.as_ptr(),
+                    size: frame.len(),
+                },
+                x_offset: 0,
+                y_offset: 0,
+                duration: 100,
+                id: WEBP_CHUNK_ANMF,
+                dispose_method: WEBP_MUX_DISPOSE_NONE,
+                blend_method: WEBP_MUX_BLEND,
+                pad: [0],
+            };
+            assert_eq!(WebPMuxPushFrame(mux, &info, 1), WEBP_MUX_OK);
+            assert_eq!(WebPMuxSetCanvasSize(mux, 70000, 1), WEBP_MUX_OK);
+            let params = WebPMuxAnimParams {
+                bgcolor: 0,
+                loop_count: 0,
+            };
+            assert_eq!(WebPMuxSetAnimationParams(mux, &params), WEBP_MUX_OK);
+            let mut assembled = WebPData {
+                bytes: std::ptr::null(),
+                size: 0,
+            };
+            assert_eq!(WebPMuxAssemble(mux, &mut assembled), WEBP_MUX_OK);
+            let webp = std::slice::from_raw_parts(assembled.bytes, assembled.size).to_vec();
+            WebPDataClear(&mut assembled);
+            WebPMuxDelete(mux);
+            webp
+        };
+        assert!(matches!(
+            webp_to_gif(&webp, &mut Vec::new(), 128),
+            Err(GifError::TooLarge(70000, 1))
+        ));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let (options, config) = config();
+        let truncated = &sample(None)[..30];
+        assert!(matches!(
+            gif_to_webp(truncated, &options, &config),
+            Err(GifError::Decode(_))
+        ));
+    }
+}
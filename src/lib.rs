@@ -1,9 +1,13 @@
 #![cfg_attr(feature = "__doc_cfg", feature(doc_cfg))]
 #![cfg_attr(feature = "extern-types", feature(extern_types))]
+#![allow(clippy::missing_safety_doc)]
 
 #[macro_use]
 extern crate cfg_if;
 
+#[macro_use]
+mod macros;
+
 pub use crate::decode::*;
 #[cfg(feature = "demux")]
 pub use crate::demux::*;
@@ -12,14 +16,75 @@ pub use crate::encode::*;
 pub use crate::mux::*;
 #[cfg(any(feature = "mux", feature = "demux"))]
 pub use crate::mux_types::*;
+#[cfg(feature = "sharpyuv")]
+pub use crate::sharpyuv::*;
 pub use crate::types::*;
 
+pub mod analysis;
+#[cfg(all(feature = "demux", libwebp_0_5))]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "demux", feature = "0_5")))
+)]
+pub mod anim;
+#[cfg(all(feature = "png", feature = "demux", libwebp_0_5))]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "png", feature = "demux", feature = "0_5")))
+)]
+pub mod anim_dump;
+#[cfg(all(feature = "mux", libwebp_0_5))]
+#[cfg_attr(feature = "__doc_cfg", doc(cfg(all(feature = "mux", feature = "0_5"))))]
+pub mod anim_encoder;
+#[cfg(feature = "rayon")]
+#[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "rayon")))]
+pub mod batch;
+pub mod budget;
+#[cfg(all(any(feature = "gif", feature = "png"), feature = "mux", libwebp_0_5))]
+mod canvas;
+#[cfg(feature = "image")]
+#[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "image")))]
+pub mod codec;
+pub mod colorspace;
 mod decode;
+pub mod decoder;
 #[cfg(feature = "demux")]
 mod demux;
+#[cfg(feature = "dynamic")]
+#[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "dynamic")))]
+pub mod dynamic;
 mod encode;
+pub mod encoder;
+#[cfg(all(feature = "gif", feature = "mux", libwebp_0_5))]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "gif", feature = "mux", feature = "0_5")))
+)]
+pub mod gif;
+pub mod metrics;
 #[cfg(feature = "mux")]
 mod mux;
 #[cfg(any(feature = "mux", feature = "demux"))]
 mod mux_types;
+#[cfg(feature = "pixels")]
+#[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "pixels")))]
+pub mod pixel;
+#[cfg(all(feature = "png", feature = "mux", libwebp_0_5))]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "png", feature = "mux", feature = "0_5")))
+)]
+pub mod png;
+#[cfg(feature = "sharpyuv")]
+mod sharpyuv;
+pub mod stats;
+#[cfg(all(feature = "demux", feature = "mux", libwebp_0_5))]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "demux", feature = "mux", feature = "0_5")))
+)]
+pub mod transcode;
 mod types;
+pub mod version;
+#[cfg(any(all(target_arch = "wasm32", target_os = "unknown"), test))]
+mod wasm_shim;
@@ -0,0 +1,80 @@
+/// Declares libwebp functions. Without the `dynamic` feature this is a plain
+/// `extern "C"` block.
+#[cfg(not(feature = "dynamic"))]
+macro_rules! webp_extern {
+    ($($item:tt)*) => {
+        extern "C" {
+            $($item)*
+        }
+    };
+}
+
+/// Declares libwebp functions. With the `dynamic` feature, each function is
+/// resolved from the library loaded by `dynamic::load` on its first call.
+/// A module of the same name gives access to the function pointer without
+/// panicking when the symbol is missing.
+#[cfg(feature = "dynamic")]
+macro_rules! webp_extern {
+    ($(
+        $(#[$attr:meta])*
+        pub fn $name:ident($($arg:ident: $ty:ty),* $(,)*) $(-> $ret:ty)*;
+    )*) => {
+        $(
+            $(#[$attr])*
+            // `deprecated` for the companion module, which shares the attributes.
+            #[allow(non_snake_case, deprecated, clippy::too_many_arguments)]
+            #[inline]
+            pub unsafe fn $name($($arg: $ty),*) $(-> $ret)* {
+                match $name::get() {
+                    Ok(f) => f($($arg),*),
+                    Err(e) => panic!("{}", e),
+                }
+            }
+
+            $(#[$attr])*
+            #[allow(non_snake_case)]
+            pub mod $name {
+                #[allow(unused_imports)]
+                use super::*;
+                use std::sync::atomic::AtomicUsize;
+
+                static ADDRESS: AtomicUsize = AtomicUsize::new(0);
+
+                /// Type of the function pointer.
+                pub type Function = unsafe extern "C" fn($($ty),*) $(-> $ret)*;
+
+                /// Resolves the function from the loaded libwebp.
+                pub fn get() -> Result<Function, crate::dynamic::Error> {
+                    let address = crate::dynamic::resolve(&ADDRESS, stringify!($name))?;
+                    Ok(unsafe { std::mem::transmute::<usize, Function>(address) })
+                }
+
+                /// Whether the loaded libwebp provides the function.
+                pub fn is_loaded() -> bool {
+                    get().is_ok()
+                }
+            }
+        )*
+    };
+}
+
+/// Calls a libwebp function from one of the inline `extern "C"` helpers.
+#[cfg(not(feature = "dynamic"))]
+macro_rules! webp_call {
+    ($failure:expr, $name:ident($($arg:expr),* $(,)*)) => {
+        $name($($arg),*)
+    };
+}
+
+/// Calls a libwebp function from one of the inline `extern "C"` helpers. A
+/// panic cannot unwind out of them, so with the `dynamic` feature this
+/// evaluates to `$failure` if the function cannot be loaded.
+#[cfg(feature = "dynamic")]
+macro_rules! webp_call {
+    ($failure:expr, $name:ident($($arg:expr),* $(,)*)) => {
+        match $name::get() {
+            Ok(f) => f($($arg),*),
+            Err(_) => $failure,
+        }
+    };
+}
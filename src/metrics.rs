@@ -0,0 +1,260 @@
+//! Image quality metrics.
+//!
+//! [`compare`] measures two pictures with `WebPPictureDistortion`, always in
+//! ARGB, and [`compare_planes`] single 8-bit planes with
+//! `WebPPlaneDistortion`. All results are in dB; identical inputs give 99.
+
+use std::error::Error;
+use std::fmt;
+use std::os::raw::*;
+
+use crate::decoder::{self, DecodeError, PixelLayout};
+use crate::encode::*;
+use crate::encoder::{EncodeError, Picture};
+
+/// Distortion metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Metric {
+    /// Peak signal-to-noise ratio.
+    Psnr,
+    /// Structural similarity, as `-10 log10(1 - ssim)`.
+    Ssim,
+    /// Local-min SSIM.
+    Lsim,
+}
+
+impl Metric {
+    fn raw(self) -> c_int {
+        match self {
+            Metric::Psnr => 0,
+            Metric::Ssim => 1,
+            Metric::Lsim => 2,
+        }
+    }
+}
+
+/// Per-channel and overall result of [`compare`], in dB.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Distortion {
+    pub blue: f32,
+    pub green: f32,
+    pub red: f32,
+    pub alpha: f32,
+    pub all: f32,
+}
+
+/// Errors of the comparisons.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompareError {
+    /// The inputs differ in size.
+    DimensionMismatch,
+    /// A plane is smaller than its dimensions and stride.
+    BufferTooSmall,
+    /// libwebp failed, e.g. allocating the ARGB conversion.
+    Failed,
+    Encode(EncodeError),
+    Decode(DecodeError),
+}
+
+impl fmt::Display for CompareError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompareError::DimensionMismatch => f.write_str("pictures differ in size"),
+            CompareError::BufferTooSmall => f.write_str("plane buffer too small"),
+            CompareError::Failed => f.write_str("distortion computation failed"),
+            CompareError::Encode(e) => e.fmt(f),
+            CompareError::Decode(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for CompareError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CompareError::Encode(e) => Some(e),
+            CompareError::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<EncodeError> for CompareError {
+    fn from(e: EncodeError) -> CompareError {
+        CompareError::Encode(e)
+    }
+}
+
+impl From<DecodeError> for CompareError {
+    fn from(e: DecodeError) -> CompareError {
+        CompareError::Decode(e)
+    }
+}
+
+/// Measures the distortion of `b` against `a`.
+pub fn compare(a: &Picture, b: &Picture, metric: Metric) -> Result<Distortion, CompareError> {
+    if a.width() != b.width() || a.height() != b.height() {
+        return Err(CompareError::DimensionMismatch);
+    }
+    let mut result = [0.0f32; 5];
+    let ok =
+        unsafe { WebPPictureDistortion(a.as_raw(), b.as_raw(), metric.raw(), result.as_mut_ptr()) };
+    if ok == 0 {
+        return Err(CompareError::Failed);
+    }
+    Ok(Distortion {
+        blue: result[0],
+        green: result[1],
+        red: result[2],
+        alpha: result[3],
+        all: result[4],
+    })
+}
+
+/// Decodes a WebP image into an ARGB picture.
+pub(crate) fn decode_picture(webp: &[u8]) -> Result<Picture, CompareError> {
+    let image = decoder::decode(webp, PixelLayout::Rgba)?;
+    Ok(Picture::from_rgba(&image.data, image.width, image.height)?)
+}
+
+/// Decodes `webp` and measures its distortion against `source`, like
+/// `cwebp -print_psnr`.
+pub fn compare_encoded(
+    source: &Picture,
+    webp: &[u8],
+    metric: Metric,
+) -> Result<Distortion, CompareError> {
+    compare(source, &decode_picture(webp)?, metric)
+}
+
+/// An 8-bit plane, or one channel of interleaved samples.
+#[cfg(libwebp_0_6)]
+#[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "0_6")))]
+#[derive(Debug, Clone, Copy)]
+pub struct Plane<'a> {
+    pub data: &'a [u8],
+    /// Distance between rows in bytes.
+    pub stride: usize,
+}
+
+/// Result of [`compare_planes`].
+#[cfg(libwebp_0_6)]
+#[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "0_6")))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaneDistortion {
+    /// The raw distortion (sum of squared errors for PSNR).
+    pub distortion: f32,
+    /// The distortion in dB.
+    pub result: f32,
+}
+
+/// Measures the distortion of `b` against `a` over `width` × `height`
+/// samples that are `x_step` bytes apart in each row.
+#[cfg(libwebp_0_6)]
+#[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "0_6")))]
+pub fn compare_planes(
+    a: Plane<'_>,
+    b: Plane<'_>,
+    width: u32,
+    height: u32,
+    x_step: usize,
+    metric: Metric,
+) -> Result<PlaneDistortion, CompareError> {
+    if width == 0 || height == 0 || x_step == 0 {
+        return Err(CompareError::DimensionMismatch);
+    }
+    let row = (width as usize - 1) * x_step + 1;
+    for plane in &[a, b] {
+        if plane.stride < row || plane.data.len() < plane.stride * (height as usize - 1) + row {
+            return Err(CompareError::BufferTooSmall);
+        }
+    }
+    let mut distortion = 0.0f32;
+    let mut result = 0.0f32;
+    let ok = unsafe {
+        WebPPlaneDistortion(
+            a.data.as_ptr(),
+            a.stride,
+            b.data.as_ptr(),
+            b.stride,
+            width as c_int,
+            height as c_int,
+            x_step,
+            metric.raw(),
+            &mut distortion,
+            &mut result,
+        )
+    };
+    if ok == 0 {
+        return Err(CompareError::Failed);
+    }
+    Ok(PlaneDistortion { distortion, result })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::tests::gradient;
+    use crate::encoder::EncoderConfig;
+
+    #[test]
+    fn test_compare() {
+        let rgba = gradient(32, 32, 255);
+        let a = Picture::from_rgba(&rgba, 32, 32).unwrap();
+        let same = compare(&a, &a.try_clone().unwrap(), Metric::Psnr).unwrap();
+        assert_eq!(same.all, 99.0);
+
+        let noisy: Vec<u8> = rgba.iter().map(|&v| v ^ 3).collect();
+        let b = Picture::from_rgba(&noisy, 32, 32).unwrap();
+        let psnr = compare(&a, &b, Metric::Psnr).unwrap();
+        assert!(psnr.all > 30.0 && psnr.all < 99.0, "{:?}", psnr);
+        assert!(psnr.red < 99.0 && psnr.alpha < 99.0);
+        assert!(compare(&a, &b, Metric::Ssim).unwrap().all > 0.0);
+        assert!(compare(&a, &b, Metric::Lsim).is_ok());
+
+        let small = Picture::new(16, 32).unwrap();
+        assert_eq!(
+            compare(&a, &small, Metric::Psnr),
+            Err(CompareError::DimensionMismatch)
+        );
+    }
+
+    #[test]
+    fn test_compare_encoded() {
+        let source = Picture::from_rgba(&gradient(32, 32, 255), 32, 32).unwrap();
+        let mut work = source.try_clone().unwrap();
+        let low = work.encode(&EncoderConfig::lossy(5.0).unwrap()).unwrap();
+        let high = work.encode(&EncoderConfig::lossy(95.0).unwrap()).unwrap();
+        let low = compare_encoded(&source, &low, Metric::Psnr).unwrap();
+        let high = compare_encoded(&source, &high, Metric::Psnr).unwrap();
+        assert!(low.all < high.all);
+        assert!(compare_encoded(&source, b"RIFF", Metric::Psnr).is_err());
+    }
+
+    #[cfg(libwebp_0_6)]
+    #[test]
+    fn test_compare_planes() {
+        let rgba = gradient(32, 32, 255);
+        let noisy: Vec<u8> = rgba.iter().map(|&v| v ^ 1).collect();
+        let a = Plane {
+            data: &rgba,
+            stride: 32 * 4,
+        };
+        let b = Plane {
+            data: &noisy,
+            stride: 32 * 4,
+        };
+        let green = compare_planes(a, b, 32, 32, 4, Metric::Psnr).unwrap();
+        assert!(green.result > 40.0 && green.result < 99.0);
+        assert!(green.distortion > 0.0);
+        let same = compare_planes(a, a, 32, 32, 4, Metric::Psnr).unwrap();
+        assert_eq!(same.distortion, 0.0);
+        let short = Plane {
+            data: &rgba[..100],
+            stride: 32 * 4,
+        };
+        assert_eq!(
+            compare_planes(a, short, 32, 32, 4, Metric::Psnr),
+            Err(CompareError::BufferTooSmall)
+        );
+    }
+}
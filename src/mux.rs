@@ -1,6 +1,6 @@
 use std::os::raw::*;
 
-#[cfg(feature = "0_5")]
+#[cfg(libwebp_0_5)]
 use crate::encode::{WebPConfig, WebPPicture};
 use crate::mux_types::*;
 
@@ -8,9 +8,9 @@ use crate::mux_types::*;
 pub const WEBP_MUX_ABI_VERSION: c_int = WEBP_MUX_ABI_VERSION_INTERNAL;
 
 cfg_if! {
-    if #[cfg(feature = "0_6")] {
+    if #[cfg(libwebp_0_6)] {
         const WEBP_MUX_ABI_VERSION_INTERNAL: c_int = 0x0108;
-    } else if #[cfg(feature = "0_5")] {
+    } else if #[cfg(libwebp_0_5)] {
         const WEBP_MUX_ABI_VERSION_INTERNAL: c_int = 0x0106;
     } else {
         const WEBP_MUX_ABI_VERSION_INTERNAL: c_int = 0x0101;
@@ -53,10 +53,10 @@ pub const WEBP_CHUNK_VP8X: WebPChunkId = 0;
 pub const WEBP_CHUNK_ICCP: WebPChunkId = 1;
 pub const WEBP_CHUNK_ANIM: WebPChunkId = 2;
 pub const WEBP_CHUNK_ANMF: WebPChunkId = 3;
-#[cfg(not(feature = "0_6"))]
+#[cfg(not(libwebp_0_6))]
 #[deprecated(note = "Removed as of libwebp 0.6.0")]
 pub const WEBP_CHUNK_FRGM: WebPChunkId = 4;
-#[cfg(feature = "0_6")]
+#[cfg(libwebp_0_6)]
 pub const WEBP_CHUNK_DEPRECATED: WebPChunkId = 4;
 pub const WEBP_CHUNK_ALPHA: WebPChunkId = 5;
 pub const WEBP_CHUNK_IMAGE: WebPChunkId = 6;
@@ -88,18 +88,18 @@ pub struct WebPMuxAnimParams {
     pub loop_count: c_int,
 }
 
-#[cfg(all(feature = "0_5", feature = "extern-types"))]
+#[cfg(all(libwebp_0_5, feature = "extern-types"))]
 extern "C" {
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(all(feature = "mux", feature = "0_5"))))]
     pub type WebPAnimEncoder;
 }
 
-#[cfg(all(feature = "0_5", not(feature = "extern-types")))]
+#[cfg(all(libwebp_0_5, not(feature = "extern-types")))]
 #[cfg_attr(feature = "__doc_cfg", doc(cfg(all(feature = "mux", feature = "0_5"))))]
 #[repr(C)]
 pub struct WebPAnimEncoder(c_void);
 
-#[cfg(feature = "0_5")]
+#[cfg(libwebp_0_5)]
 #[cfg_attr(feature = "__doc_cfg", doc(cfg(all(feature = "mux", feature = "0_5"))))]
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -114,17 +114,21 @@ pub struct WebPAnimEncoderOptions {
     pub padding: [u32; 4],
 }
 
-extern "C" {
+webp_extern! {
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "mux")))]
     pub fn WebPGetMuxVersion() -> c_int;
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "mux")))]
     #[doc(hidden)]
-    pub fn WebPNewInternal(_: c_int) -> *mut WebPMux;
+    pub fn WebPNewInternal(version: c_int) -> *mut WebPMux;
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "mux")))]
     pub fn WebPMuxDelete(mux: *mut WebPMux);
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "mux")))]
     #[doc(hidden)]
-    pub fn WebPMuxCreateInternal(_: *const WebPData, _: c_int, _: c_int) -> *mut WebPMux;
+    pub fn WebPMuxCreateInternal(
+        bitstream: *const WebPData,
+        copy_data: c_int,
+        version: c_int,
+    ) -> *mut WebPMux;
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "mux")))]
     pub fn WebPMuxSetChunk(
         mux: *mut WebPMux,
@@ -170,7 +174,7 @@ extern "C" {
         mux: *const WebPMux,
         params: *mut WebPMuxAnimParams,
     ) -> WebPMuxError;
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(all(feature = "mux", feature = "0_5"))))]
     pub fn WebPMuxSetCanvasSize(mux: *mut WebPMux, width: c_int, height: c_int) -> WebPMuxError;
     pub fn WebPMuxGetCanvasSize(
@@ -188,20 +192,23 @@ extern "C" {
     ) -> WebPMuxError;
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "mux")))]
     pub fn WebPMuxAssemble(mux: *mut WebPMux, assembled_data: *mut WebPData) -> WebPMuxError;
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(all(feature = "mux", feature = "0_5"))))]
     #[doc(hidden)]
-    pub fn WebPAnimEncoderOptionsInitInternal(_: *mut WebPAnimEncoderOptions, _: c_int) -> c_int;
-    #[cfg(feature = "0_5")]
+    pub fn WebPAnimEncoderOptionsInitInternal(
+        enc_options: *mut WebPAnimEncoderOptions,
+        version: c_int,
+    ) -> c_int;
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(all(feature = "mux", feature = "0_5"))))]
     #[doc(hidden)]
     pub fn WebPAnimEncoderNewInternal(
-        _: c_int,
-        _: c_int,
-        _: *const WebPAnimEncoderOptions,
-        _: c_int,
+        width: c_int,
+        height: c_int,
+        enc_options: *const WebPAnimEncoderOptions,
+        version: c_int,
     ) -> *mut WebPAnimEncoder;
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(all(feature = "mux", feature = "0_5"))))]
     pub fn WebPAnimEncoderAdd(
         enc: *mut WebPAnimEncoder,
@@ -209,13 +216,13 @@ extern "C" {
         timestamp_ms: c_int,
         config: *const WebPConfig,
     ) -> c_int;
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(all(feature = "mux", feature = "0_5"))))]
     pub fn WebPAnimEncoderAssemble(enc: *mut WebPAnimEncoder, webp_data: *mut WebPData) -> c_int;
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(all(feature = "mux", feature = "0_5"))))]
     pub fn WebPAnimEncoderGetError(enc: *mut WebPAnimEncoder) -> *const c_char;
-    #[cfg(feature = "0_5")]
+    #[cfg(libwebp_0_5)]
     #[cfg_attr(feature = "__doc_cfg", doc(cfg(all(feature = "mux", feature = "0_5"))))]
     pub fn WebPAnimEncoderDelete(enc: *mut WebPAnimEncoder);
 }
@@ -224,7 +231,7 @@ extern "C" {
 #[allow(non_snake_case)]
 #[inline]
 pub unsafe extern "C" fn WebPMuxNew() -> *mut WebPMux {
-    WebPNewInternal(WEBP_MUX_ABI_VERSION)
+    webp_call!(std::ptr::null_mut(), WebPNewInternal(WEBP_MUX_ABI_VERSION))
 }
 
 #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "mux")))]
@@ -234,20 +241,26 @@ pub unsafe extern "C" fn WebPMuxCreate(
     bitstream: *const WebPData,
     copy_data: c_int,
 ) -> *mut WebPMux {
-    WebPMuxCreateInternal(bitstream, copy_data, WEBP_MUX_ABI_VERSION)
+    webp_call!(
+        std::ptr::null_mut(),
+        WebPMuxCreateInternal(bitstream, copy_data, WEBP_MUX_ABI_VERSION)
+    )
 }
 
-#[cfg(feature = "0_5")]
+#[cfg(libwebp_0_5)]
 #[cfg_attr(feature = "__doc_cfg", doc(cfg(all(feature = "mux", feature = "0_5"))))]
 #[allow(non_snake_case)]
 #[inline]
 pub unsafe extern "C" fn WebPAnimEncoderOptionsInit(
     enc_options: *mut WebPAnimEncoderOptions,
 ) -> c_int {
-    WebPAnimEncoderOptionsInitInternal(enc_options, WEBP_MUX_ABI_VERSION)
+    webp_call!(
+        0,
+        WebPAnimEncoderOptionsInitInternal(enc_options, WEBP_MUX_ABI_VERSION)
+    )
 }
 
-#[cfg(feature = "0_5")]
+#[cfg(libwebp_0_5)]
 #[cfg_attr(feature = "__doc_cfg", doc(cfg(all(feature = "mux", feature = "0_5"))))]
 #[allow(non_snake_case)]
 #[inline]
@@ -256,7 +269,10 @@ pub unsafe extern "C" fn WebPAnimEncoderNew(
     height: c_int,
     enc_options: *const WebPAnimEncoderOptions,
 ) -> *mut WebPAnimEncoder {
-    WebPAnimEncoderNewInternal(width, height, enc_options, WEBP_MUX_ABI_VERSION)
+    webp_call!(
+        std::ptr::null_mut(),
+        WebPAnimEncoderNewInternal(width, height, enc_options, WEBP_MUX_ABI_VERSION)
+    )
 }
 
 #[cfg(test)]
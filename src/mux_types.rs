@@ -1,17 +1,15 @@
-use std::mem;
 use std::os::raw::*;
+use std::ptr;
 
-use libc::{memcpy, memset};
-
-#[cfg(feature = "1_1")]
+#[cfg(libwebp_1_1)]
 use crate::{WebPFree, WebPMalloc};
-#[cfg(not(feature = "1_1"))]
-use libc::{free as WebPFree, malloc as WebPMalloc};
+#[cfg(not(libwebp_1_1))]
+use libc::{free, malloc};
 
 #[allow(non_camel_case_types)]
 pub type WebPFeatureFlags = u32;
 
-#[cfg(not(feature = "0_6"))]
+#[cfg(not(libwebp_0_6))]
 #[deprecated(note = "Removed as of libwebp 0.6.0")]
 pub const FRAGMENTS_FLAG: WebPFeatureFlags = 0x00000001;
 pub const ANIMATION_FLAG: WebPFeatureFlags = 0x00000002;
@@ -19,7 +17,7 @@ pub const XMP_FLAG: WebPFeatureFlags = 0x00000004;
 pub const EXIF_FLAG: WebPFeatureFlags = 0x00000008;
 pub const ALPHA_FLAG: WebPFeatureFlags = 0x00000010;
 pub const ICCP_FLAG: WebPFeatureFlags = 0x00000020;
-#[cfg(feature = "0_6")]
+#[cfg(libwebp_0_6)]
 pub const ALL_VALID_FLAGS: WebPFeatureFlags = 0x0000003E;
 
 #[allow(non_camel_case_types)]
@@ -41,11 +39,21 @@ pub struct WebPData {
     pub size: usize,
 }
 
+#[cfg(libwebp_1_1)]
+unsafe fn free(ptr: *mut c_void) {
+    webp_call!((), WebPFree(ptr))
+}
+
+#[cfg(libwebp_1_1)]
+unsafe fn malloc(size: usize) -> *mut c_void {
+    webp_call!(ptr::null_mut(), WebPMalloc(size))
+}
+
 #[allow(non_snake_case)]
 #[inline]
 pub unsafe extern "C" fn WebPDataInit(webp_data: *mut WebPData) {
     if !webp_data.is_null() {
-        memset(webp_data as *mut c_void, 0, mem::size_of::<WebPData>());
+        ptr::write_bytes(webp_data, 0, 1);
     }
 }
 
@@ -55,7 +63,7 @@ pub unsafe extern "C" fn WebPDataInit(webp_data: *mut WebPData) {
 #[inline]
 pub unsafe extern "C" fn WebPDataClear(webp_data: *mut WebPData) {
     if !webp_data.is_null() {
-        WebPFree((*webp_data).bytes as *mut c_void);
+        free((*webp_data).bytes as *mut c_void);
         WebPDataInit(webp_data);
     }
 }
@@ -70,16 +78,12 @@ pub unsafe extern "C" fn WebPDataCopy(src: *const WebPData, dst: *mut WebPData)
     }
     WebPDataInit(dst);
     if !(*src).bytes.is_null() && (*src).size != 0 {
-        (*dst).bytes = WebPMalloc((*src).size) as *mut u8;
+        (*dst).bytes = malloc((*src).size) as *mut u8;
         if (*dst).bytes.is_null() {
             return 0;
         }
-        memcpy(
-            (*dst).bytes as *mut c_void,
-            (*src).bytes as *const c_void,
-            (*src).size,
-        );
+        ptr::copy_nonoverlapping((*src).bytes, (*dst).bytes as *mut u8, (*src).size);
         (*dst).size = (*src).size;
     }
-    return 1;
+    1
 }
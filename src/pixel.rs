@@ -0,0 +1,141 @@
+//! Typed pixels from the `rgb` and `imgref` crates (the `pixels` feature).
+//!
+//! [`Decoder::decode_img`] returns an `ImgVec` of any [`Pixel`] type and
+//! [`Picture::from_img`] imports an `ImgRef`. The stride of the image, in
+//! pixels, becomes the byte stride passed to libwebp, so the byte order and
+//! row padding always match the type.
+
+use std::mem;
+use std::slice;
+
+use imgref::{ImgRef, ImgRefMut, ImgVec};
+use rgb::alt::{BGR8, BGRA8};
+use rgb::{RGB8, RGBA8};
+
+use crate::decoder::{DecodeError, Decoder, PixelLayout};
+use crate::encoder::{EncodeError, Picture};
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// 8-bit pixel types with a libwebp byte order.
+pub trait Pixel: private::Sealed + Copy + Default {
+    const LAYOUT: PixelLayout;
+}
+
+macro_rules! impl_pixel {
+    ($($ty:ty => $layout:ident,)*) => {
+        $(
+            impl private::Sealed for $ty {}
+            impl Pixel for $ty {
+                const LAYOUT: PixelLayout = PixelLayout::$layout;
+            }
+        )*
+    };
+}
+
+impl_pixel! {
+    RGB8 => Rgb,
+    RGBA8 => Rgba,
+    BGR8 => Bgr,
+    BGRA8 => Bgra,
+}
+
+fn as_bytes<P: Pixel>(pixels: &[P]) -> &[u8] {
+    // Safety: the pixel types are `repr(C)` structs of `u8`.
+    unsafe { slice::from_raw_parts(pixels.as_ptr() as *const u8, mem::size_of_val(pixels)) }
+}
+
+fn as_bytes_mut<P: Pixel>(pixels: &mut [P]) -> &mut [u8] {
+    unsafe { slice::from_raw_parts_mut(pixels.as_mut_ptr() as *mut u8, mem::size_of_val(pixels)) }
+}
+
+impl Decoder<'_> {
+    /// Decodes the image into a new `ImgVec`.
+    pub fn decode_img<P: Pixel>(&self) -> Result<ImgVec<P>, DecodeError> {
+        let features = self.features();
+        let (width, height) = (features.width as usize, features.height as usize);
+        let stride = width * mem::size_of::<P>();
+        let mut buf = vec![P::default(); self.output_size(stride)? / mem::size_of::<P>()];
+        self.decode_img_into(ImgRefMut::new(&mut buf, width, height))?;
+        Ok(ImgVec::new(buf, width, height))
+    }
+
+    /// Decodes the image into `img`, which must have the dimensions of the
+    /// image.
+    pub fn decode_img_into<P: Pixel>(&self, mut img: ImgRefMut<'_, P>) -> Result<(), DecodeError> {
+        let features = self.features();
+        if img.width() != features.width as usize || img.height() != features.height as usize {
+            return Err(DecodeError::BufferTooSmall);
+        }
+        let stride = img.stride() * mem::size_of::<P>();
+        self.decode_into(P::LAYOUT, as_bytes_mut(img.buf_mut()), stride)
+    }
+}
+
+impl Picture {
+    /// Imports an image into an ARGB picture.
+    pub fn from_img<P: Pixel>(img: ImgRef<'_, P>) -> Result<Picture, EncodeError> {
+        Picture::import(
+            P::LAYOUT,
+            as_bytes(img.buf()),
+            img.width() as u32,
+            img.height() as u32,
+            img.stride() * mem::size_of::<P>(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::EncoderConfig;
+    use imgref::Img;
+
+    #[test]
+    fn test_roundtrip_with_stride() {
+        let width = 5;
+        let stride = 8;
+        let buf: Vec<RGBA8> = (0..stride * 3)
+            .map(|i| RGBA8::new(i as u8, 2 * i as u8, 3 * i as u8, 255 - i as u8))
+            .collect();
+        let img = Img::new_stride(&buf[..], width, 3, stride);
+        let webp = Picture::from_img(img)
+            .unwrap()
+            .encode(&EncoderConfig::lossless(50.0).unwrap())
+            .unwrap();
+
+        let decoder = Decoder::new(&webp).unwrap();
+        let decoded = decoder.decode_img::<RGBA8>().unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (width, 3));
+        assert!(decoded.rows().eq(img.rows()));
+
+        let bgra = decoder.decode_img::<BGRA8>().unwrap();
+        assert_eq!(bgra.buf()[1].b, img.buf()[1].b);
+        assert_eq!(bgra.buf()[1].r, img.buf()[1].r);
+
+        let mut out = vec![RGB8::default(); stride * 2 + width];
+        decoder
+            .decode_img_into(Img::new_stride(&mut out[..], width, 3, stride))
+            .unwrap();
+        let p = img[(4usize, 2usize)];
+        assert_eq!(out[stride * 2 + 4], RGB8::new(p.r, p.g, p.b));
+    }
+
+    #[test]
+    fn test_dimension_mismatch() {
+        let img = Img::new(vec![RGB8::default(); 4], 2, 2);
+        let webp = Picture::from_img(img.as_ref())
+            .unwrap()
+            .encode(&EncoderConfig::new().unwrap())
+            .unwrap();
+        let mut buf = [RGB8::default(); 9];
+        assert_eq!(
+            Decoder::new(&webp)
+                .unwrap()
+                .decode_img_into(Img::new(&mut buf[..], 3, 3)),
+            Err(DecodeError::BufferTooSmall)
+        );
+    }
+}
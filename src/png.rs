@@ -0,0 +1,289 @@
+//! APNG conversion (the `png` feature, with `mux`).
+//!
+//! [`apng_to_webp`] composes every APNG frame onto a full canvas according
+//! to its `dispose_op` and `blend_op`, and gives the canvases to
+//! [`AnimEncoder`] at the times given by the `fcTL` delays.
+
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use std::time::Duration;
+
+use ::png::{BlendOp, ColorType, Decoder, DecodingError, DisposeOp, FrameControl, Transformations};
+
+use crate::anim_encoder::{AnimEncodeError, AnimEncoder, AnimEncoderOptions};
+use crate::canvas::{Canvas, Rect};
+use crate::encoder::EncoderConfig;
+
+/// Errors of [`apng_to_webp`].
+#[derive(Debug)]
+pub enum PngError {
+    Decode(DecodingError),
+    Encode(AnimEncodeError),
+}
+
+impl fmt::Display for PngError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PngError::Decode(e) => e.fmt(f),
+            PngError::Encode(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for PngError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PngError::Decode(e) => Some(e),
+            PngError::Encode(e) => Some(e),
+        }
+    }
+}
+
+impl From<DecodingError> for PngError {
+    fn from(e: DecodingError) -> PngError {
+        PngError::Decode(e)
+    }
+}
+
+impl From<AnimEncodeError> for PngError {
+    fn from(e: AnimEncodeError) -> PngError {
+        PngError::Encode(e)
+    }
+}
+
+/// Converts an APNG to an animated WebP.
+///
+/// Each frame is encoded with `config`. The loop count of `options` is
+/// replaced by the `num_plays` of the APNG. The default image is skipped if
+/// it is not part of the animation, and a PNG without `acTL` becomes a
+/// single frame.
+pub fn apng_to_webp<R: Read>(
+    reader: R,
+    options: &AnimEncoderOptions,
+    config: &EncoderConfig,
+) -> Result<Vec<u8>, PngError> {
+    let mut decoder = Decoder::new(reader);
+    decoder.set_transformations(Transformations::normalize_to_color8() | Transformations::ALPHA);
+    let mut reader = decoder.read_info()?;
+    let info = reader.info();
+    let (width, height) = (info.width, info.height);
+    let (frame_count, hidden_default) = match info.animation_control {
+        Some(actl) => (actl.num_frames, info.frame_control.is_none()),
+        None => (1, false),
+    };
+    let mut options = *options;
+    if let Some(actl) = info.animation_control {
+        options.set_loop_count(actl.num_plays);
+    }
+    let mut encoder = AnimEncoder::new(width, height, &options)?;
+
+    let mut canvas = Canvas::new(width, height);
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    if hidden_default {
+        reader.next_frame(&mut buffer)?;
+    }
+    let mut timestamp = Duration::from_millis(0);
+    for index in 0..frame_count {
+        let output = reader.next_frame(&mut buffer)?;
+        let control = match reader.info().frame_control {
+            Some(control) => control,
+            None => FrameControl {
+                width,
+                height,
+                ..FrameControl::default()
+            },
+        };
+        let frame = Frame {
+            control,
+            data: &buffer[..output.buffer_size()],
+            gray: output.color_type == ColorType::GrayscaleAlpha,
+        };
+        // Previous on the first frame is treated as Background.
+        let dispose = match control.dispose_op {
+            DisposeOp::Previous if index == 0 => DisposeOp::Background,
+            dispose => dispose,
+        };
+        let previous = match dispose {
+            DisposeOp::Previous => Some(canvas.pixels.clone()),
+            _ => None,
+        };
+        canvas.draw(frame.rect(), |dst, x, y| {
+            let src = frame.pixel(x, y);
+            match control.blend_op {
+                BlendOp::Source => dst.copy_from_slice(&src),
+                BlendOp::Over => blend_over(dst, src),
+            }
+        });
+
+        let mut picture = canvas.to_picture()?;
+        encoder.add_frame(&mut picture, timestamp, config)?;
+
+        match dispose {
+            DisposeOp::Background => canvas.clear(frame.rect()),
+            DisposeOp::Previous => canvas.pixels = previous.unwrap(),
+            DisposeOp::None => {}
+        }
+        timestamp += delay(&control);
+    }
+    Ok(encoder.finish(timestamp)?)
+}
+
+/// The delay of a frame; a denominator of 0 means hundredths of a second.
+fn delay(control: &FrameControl) -> Duration {
+    let den = if control.delay_den == 0 {
+        100
+    } else {
+        u64::from(control.delay_den)
+    };
+    Duration::from_nanos(u64::from(control.delay_num) * 1_000_000_000 / den)
+}
+
+/// A decoded frame, RGBA or gray with alpha.
+struct Frame<'a> {
+    control: FrameControl,
+    data: &'a [u8],
+    gray: bool,
+}
+
+impl Frame<'_> {
+    fn rect(&self) -> Rect {
+        Rect {
+            left: self.control.x_offset,
+            top: self.control.y_offset,
+            width: self.control.width,
+            height: self.control.height,
+        }
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let width = self.control.width as usize;
+        if self.gray {
+            let p = &self.data[(y * width + x) * 2..][..2];
+            [p[0], p[0], p[0], p[1]]
+        } else {
+            let p = &self.data[(y * width + x) * 4..][..4];
+            [p[0], p[1], p[2], p[3]]
+        }
+    }
+}
+
+/// Composes the non-premultiplied `src` over `dst`.
+fn blend_over(dst: &mut [u8], src: [u8; 4]) {
+    let src_a = u32::from(src[3]);
+    if src_a == 255 {
+        dst.copy_from_slice(&src);
+        return;
+    }
+    if src_a == 0 {
+        return;
+    }
+    // Alpha of dst that shows through, scaled by 255.
+    let dst_a = u32::from(dst[3]) * (255 - src_a);
+    let out_a = src_a * 255 + dst_a;
+    for i in 0..3 {
+        let c = u32::from(src[i]) * src_a * 255 + u32::from(dst[i]) * dst_a;
+        dst[i] = ((c + out_a / 2) / out_a) as u8;
+    }
+    dst[3] = ((out_a + 127) / 255) as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::png::Encoder;
+
+    #[cfg(feature = "demux")]
+    use crate::anim::AnimDecoder;
+
+    /// A 4×4 APNG playing twice: a hidden default image, a red background,
+    /// a green 2×2 square at (2, 2) disposed to background, then a
+    /// half-transparent blue pixel blended over the canvas.
+    fn sample() -> Vec<u8> {
+        let mut data = Vec::new();
+        {
+            let mut encoder = Encoder::new(&mut data, 4, 4);
+            encoder.set_color(ColorType::Rgba);
+            encoder.set_animated(3, 2).unwrap();
+            encoder.set_sep_def_img(true).unwrap();
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&[255; 4 * 4 * 4]).unwrap();
+
+            writer.set_frame_delay(1, 10).unwrap();
+            writer
+                .write_image_data(&[255, 0, 0, 255].repeat(16))
+                .unwrap();
+
+            writer.set_frame_dimension(2, 2).unwrap();
+            writer.set_frame_position(2, 2).unwrap();
+            writer.set_frame_delay(20, 0).unwrap();
+            writer.set_dispose_op(DisposeOp::Background).unwrap();
+            writer
+                .write_image_data(&[0, 255, 0, 255].repeat(4))
+                .unwrap();
+
+            writer.set_frame_position(0, 0).unwrap();
+            writer.set_frame_dimension(1, 1).unwrap();
+            writer.set_frame_delay(3, 10).unwrap();
+            writer.set_dispose_op(DisposeOp::None).unwrap();
+            writer.set_blend_op(BlendOp::Over).unwrap();
+            writer.write_image_data(&[0, 0, 255, 128]).unwrap();
+            writer.finish().unwrap();
+        }
+        data
+    }
+
+    fn config() -> (AnimEncoderOptions, EncoderConfig) {
+        (
+            AnimEncoderOptions::new().unwrap(),
+            EncoderConfig::lossless(50.0).unwrap(),
+        )
+    }
+
+    #[cfg(feature = "demux")]
+    #[test]
+    fn test_apng_to_webp() {
+        let (options, config) = config();
+        let webp = apng_to_webp(&sample()[..], &options, &config).unwrap();
+        let mut decoder = AnimDecoder::new(&webp).unwrap();
+        let info = decoder.info();
+        assert_eq!((info.canvas_width, info.canvas_height), (4, 4));
+        assert_eq!(info.frame_count, 3);
+        assert_eq!(info.loop_count, 2);
+        assert_eq!(info.duration, Duration::from_millis(100 + 200 + 300));
+
+        let pixel = |data: &[u8], x: usize, y: usize| data[(y * 4 + x) * 4..][..4].to_vec();
+        let first = decoder.next_frame().unwrap().unwrap();
+        assert_eq!(first.timestamp, Duration::from_millis(100));
+        assert_eq!(pixel(first.data, 0, 0), [255, 0, 0, 255]);
+        let second = decoder.next_frame().unwrap().unwrap();
+        assert_eq!(second.timestamp, Duration::from_millis(300));
+        assert_eq!(pixel(second.data, 3, 3), [0, 255, 0, 255]);
+        let third = decoder.next_frame().unwrap().unwrap();
+        assert_eq!(pixel(third.data, 3, 3), [0, 0, 0, 0]);
+        assert_eq!(pixel(third.data, 0, 0), [127, 0, 128, 255]);
+        assert_eq!(pixel(third.data, 1, 0), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_blend_over() {
+        let mut dst = [255, 0, 0, 255];
+        blend_over(&mut dst, [0, 0, 255, 0]);
+        assert_eq!(dst, [255, 0, 0, 255]);
+        blend_over(&mut dst, [0, 0, 255, 128]);
+        assert_eq!(dst, [127, 0, 128, 255]);
+        let mut dst = [0, 0, 0, 0];
+        blend_over(&mut dst, [10, 20, 30, 40]);
+        assert_eq!(dst, [10, 20, 30, 40]);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let (options, config) = config();
+        let truncated = &sample()[..60];
+        assert!(matches!(
+            apng_to_webp(truncated, &options, &config),
+            Err(PngError::Decode(_))
+        ));
+    }
+}
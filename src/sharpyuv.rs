@@ -0,0 +1,393 @@
+use std::os::raw::*;
+
+/// Version of the libsharpyuv headers these bindings follow, packed as
+/// (MAJOR << 24) | (MINOR << 16) | PATCH.
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+pub const SHARPYUV_VERSION: c_int = SHARPYUV_MAKE_VERSION(0, 4, 0);
+
+#[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "sharpyuv")))]
+#[allow(non_snake_case)]
+#[inline]
+pub const fn SHARPYUV_MAKE_VERSION(major: c_int, minor: c_int, patch: c_int) -> c_int {
+    (major << 24) | (minor << 16) | patch
+}
+
+#[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "sharpyuv")))]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SharpYuvConversionMatrix {
+    pub rgb_to_y: [c_int; 4],
+    pub rgb_to_u: [c_int; 4],
+    pub rgb_to_v: [c_int; 4],
+}
+
+/// Range of YUV values.
+#[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "sharpyuv")))]
+pub type SharpYuvRange = u32;
+
+/// YUV values between \[0;255\] (for 8 bit)
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvRangeFull: SharpYuvRange = 0;
+/// Y in \[16;235\], YUV in \[16;240\] (for 8 bit)
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvRangeLimited: SharpYuvRange = 1;
+
+/// Constants that define a YUV color space.
+#[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "sharpyuv")))]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SharpYuvColorSpace {
+    /// Kr and Kb are defined such that:
+    /// Y = Kr * r + Kg * g + Kb * b where Kg = 1 - Kr - Kb.
+    pub kr: c_float,
+    pub kb: c_float,
+    /// 8, 10 or 12
+    pub bit_depth: c_int,
+    pub range: SharpYuvRange,
+}
+
+/// Enums for precomputed conversion matrices.
+#[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "sharpyuv")))]
+pub type SharpYuvMatrixType = u32;
+
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvMatrixWebp: SharpYuvMatrixType = 0;
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvMatrixRec601Limited: SharpYuvMatrixType = 1;
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvMatrixRec601Full: SharpYuvMatrixType = 2;
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvMatrixRec709Limited: SharpYuvMatrixType = 3;
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvMatrixRec709Full: SharpYuvMatrixType = 4;
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvMatrixNum: SharpYuvMatrixType = 5;
+
+/// Enums for transfer functions, as defined in H.273,
+/// <https://www.itu.int/rec/T-REC-H.273-202107-I/en>
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+pub type SharpYuvTransferFunctionType = u32;
+
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvTransferFunctionBt709: SharpYuvTransferFunctionType = 1;
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvTransferFunctionBt470M: SharpYuvTransferFunctionType = 4;
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvTransferFunctionBt470Bg: SharpYuvTransferFunctionType = 5;
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvTransferFunctionBt601: SharpYuvTransferFunctionType = 6;
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvTransferFunctionSmpte240: SharpYuvTransferFunctionType = 7;
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvTransferFunctionLinear: SharpYuvTransferFunctionType = 8;
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvTransferFunctionLog100: SharpYuvTransferFunctionType = 9;
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvTransferFunctionLog100_Sqrt10: SharpYuvTransferFunctionType = 10;
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvTransferFunctionIec61966: SharpYuvTransferFunctionType = 11;
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvTransferFunctionBt1361: SharpYuvTransferFunctionType = 12;
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvTransferFunctionSrgb: SharpYuvTransferFunctionType = 13;
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvTransferFunctionBt2020_10Bit: SharpYuvTransferFunctionType = 14;
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvTransferFunctionBt2020_12Bit: SharpYuvTransferFunctionType = 15;
+/// PQ
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvTransferFunctionSmpte2084: SharpYuvTransferFunctionType = 16;
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvTransferFunctionSmpte428: SharpYuvTransferFunctionType = 17;
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvTransferFunctionHlg: SharpYuvTransferFunctionType = 18;
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+#[allow(non_upper_case_globals)]
+pub const kSharpYuvTransferFunctionNum: SharpYuvTransferFunctionType = 19;
+
+/// Options for SharpYuvConvertWithOptions.
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SharpYuvOptions {
+    /// This matrix cannot be NULL and can be initialized by
+    /// SharpYuvComputeConversionMatrix.
+    pub yuv_matrix: *const SharpYuvConversionMatrix,
+    pub transfer_type: SharpYuvTransferFunctionType,
+}
+
+webp_extern! {
+    /// Returns the library's version number, packed in hexadecimal. See
+    /// SHARPYUV_VERSION.
+    #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "sharpyuv")))]
+    pub fn SharpYuvGetVersion() -> c_int;
+    /// Converts RGB to YUV420 using a downsampling algorithm that minimizes
+    /// artefacts caused by chroma subsampling.
+    /// This is slower than standard downsampling (averaging of 4 UV values).
+    /// Assumes that the image will be upsampled using a bilinear filter. If nearest
+    /// neighbor is used instead, the upsampled image might look worse than with
+    /// standard downsampling.
+    /// r_ptr, g_ptr, b_ptr: pointers to the source r, g and b channels. Should point
+    ///     to uint8_t buffers if rgb_bit_depth is 8, or uint16_t buffers otherwise.
+    /// rgb_step: distance in bytes between two horizontally adjacent pixels on the
+    ///     r, g and b channels. If rgb_bit_depth is > 8, it should be a
+    ///     multiple of 2.
+    /// rgb_stride: distance in bytes between two vertically adjacent pixels on the
+    ///     r, g, and b channels. If rgb_bit_depth is > 8, it should be a
+    ///     multiple of 2.
+    /// rgb_bit_depth: number of bits for each r/g/b value. One of: 8, 10, 12, 16.
+    ///     Note: 16 bit input is truncated to 14 bits before conversion to yuv.
+    /// yuv_bit_depth: number of bits for each y/u/v value. One of: 8, 10, 12.
+    /// y_ptr, u_ptr, v_ptr: pointers to the destination y, u and v channels.  Should
+    ///     point to uint8_t buffers if yuv_bit_depth is 8, or uint16_t buffers
+    ///     otherwise.
+    /// y_stride, u_stride, v_stride: distance in bytes between two vertically
+    ///     adjacent pixels on the y, u and v channels. If yuv_bit_depth > 8, they
+    ///     should be multiples of 2.
+    /// width, height: width and height of the image in pixels
+    /// Returns true on success.
+    #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "sharpyuv")))]
+    pub fn SharpYuvConvert(
+        r_ptr: *const c_void,
+        g_ptr: *const c_void,
+        b_ptr: *const c_void,
+        rgb_step: c_int,
+        rgb_stride: c_int,
+        rgb_bit_depth: c_int,
+        y_ptr: *mut c_void,
+        y_stride: c_int,
+        u_ptr: *mut c_void,
+        u_stride: c_int,
+        v_ptr: *mut c_void,
+        v_stride: c_int,
+        yuv_bit_depth: c_int,
+        width: c_int,
+        height: c_int,
+        yuv_matrix: *const SharpYuvConversionMatrix,
+    ) -> c_int;
+    /// Fills in 'matrix' for the given YUVColorSpace.
+    #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "sharpyuv")))]
+    pub fn SharpYuvComputeConversionMatrix(
+        yuv_color_space: *const SharpYuvColorSpace,
+        matrix: *mut SharpYuvConversionMatrix,
+    );
+    /// Returns a pointer to a matrix for one of the predefined colorspaces.
+    #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "sharpyuv")))]
+    pub fn SharpYuvGetConversionMatrix(
+        matrix_type: SharpYuvMatrixType,
+    ) -> *const SharpYuvConversionMatrix;
+    /// Internal, version-checked, entry point
+    #[cfg(libwebp_1_4)]
+    #[cfg_attr(
+        feature = "__doc_cfg",
+        doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+    )]
+    #[doc(hidden)]
+    pub fn SharpYuvOptionsInitInternal(
+        yuv_matrix: *const SharpYuvConversionMatrix,
+        options: *mut SharpYuvOptions,
+        version: c_int,
+    ) -> c_int;
+    /// Same as SharpYuvConvert, but takes the conversion matrix and the transfer
+    /// function through 'options'.
+    #[cfg(libwebp_1_4)]
+    #[cfg_attr(
+        feature = "__doc_cfg",
+        doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+    )]
+    pub fn SharpYuvConvertWithOptions(
+        r_ptr: *const c_void,
+        g_ptr: *const c_void,
+        b_ptr: *const c_void,
+        rgb_step: c_int,
+        rgb_stride: c_int,
+        rgb_bit_depth: c_int,
+        y_ptr: *mut c_void,
+        y_stride: c_int,
+        u_ptr: *mut c_void,
+        u_stride: c_int,
+        v_ptr: *mut c_void,
+        v_stride: c_int,
+        yuv_bit_depth: c_int,
+        width: c_int,
+        height: c_int,
+        options: *const SharpYuvOptions,
+    ) -> c_int;
+}
+
+/// Initializes 'options' with the given matrix and the sRGB transfer function.
+/// Returns false in case of version mismatch.
+#[cfg(libwebp_1_4)]
+#[cfg_attr(
+    feature = "__doc_cfg",
+    doc(cfg(all(feature = "sharpyuv", feature = "1_4")))
+)]
+#[allow(non_snake_case)]
+#[inline]
+pub unsafe extern "C" fn SharpYuvOptionsInit(
+    yuv_matrix: *const SharpYuvConversionMatrix,
+    options: *mut SharpYuvOptions,
+) -> c_int {
+    webp_call!(
+        0,
+        SharpYuvOptionsInitInternal(yuv_matrix, options, SHARPYUV_VERSION)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::mem;
+
+    #[test]
+    fn test_convert() {
+        unsafe {
+            let matrix = SharpYuvGetConversionMatrix(kSharpYuvMatrixWebp);
+            assert!(!matrix.is_null());
+
+            let color_space = SharpYuvColorSpace {
+                kr: 0.299,
+                kb: 0.114,
+                bit_depth: 8,
+                range: kSharpYuvRangeLimited,
+            };
+            let mut computed: SharpYuvConversionMatrix = mem::zeroed();
+            SharpYuvComputeConversionMatrix(&color_space, &mut computed);
+            assert_ne!(computed.rgb_to_y, [0; 4]);
+
+            // 2x2 RGB image, packed
+            let rgb: [u8; 12] = [255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255];
+            let mut y = [0u8; 4];
+            let mut u = [0u8; 1];
+            let mut v = [0u8; 1];
+            let ok = SharpYuvConvert(
+                rgb.as_ptr() as *const c_void,
+                rgb.as_ptr().add(1) as *const c_void,
+                rgb.as_ptr().add(2) as *const c_void,
+                3,
+                6,
+                8,
+                y.as_mut_ptr() as *mut c_void,
+                2,
+                u.as_mut_ptr() as *mut c_void,
+                1,
+                v.as_mut_ptr() as *mut c_void,
+                1,
+                8,
+                2,
+                2,
+                matrix,
+            );
+            assert!(ok != 0);
+            assert!(y[3] > y[2]);
+        }
+    }
+
+    #[cfg(libwebp_1_4)]
+    #[test]
+    fn test_options_init() {
+        unsafe {
+            let matrix = SharpYuvGetConversionMatrix(kSharpYuvMatrixRec709Full);
+            let mut options = mem::zeroed();
+            assert!(SharpYuvOptionsInit(matrix, &mut options) != 0);
+            assert_eq!(options.transfer_type, kSharpYuvTransferFunctionSrgb);
+        }
+    }
+}
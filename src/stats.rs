@@ -0,0 +1,254 @@
+//! Typed encoding statistics.
+//!
+//! [`EncodeStats`] names the fields of `WebPAuxStats`. Get it from
+//! [`Picture::encode_with_stats`], or convert a raw `WebPAuxStats` with
+//! `EncodeStats::from`.
+
+use crate::encode::WebPAuxStats;
+use crate::encoder::{EncodeError, EncoderConfig, Picture};
+
+/// Statistics of one encode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EncodeStats {
+    /// Size of the output in bytes.
+    pub coded_size: usize,
+    /// PSNR of the lossy encode in dB.
+    pub psnr: PsnrStats,
+    /// Size of the compressed alpha data in bytes.
+    pub alpha_size: usize,
+    /// Present if the image was encoded lossily (VP8).
+    pub lossy: Option<LossyStats>,
+    /// Present if the image was encoded losslessly (VP8L).
+    pub lossless: Option<LosslessStats>,
+}
+
+/// PSNR per plane, in dB.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PsnrStats {
+    pub y: f32,
+    pub u: f32,
+    pub v: f32,
+    pub all: f32,
+    pub alpha: f32,
+}
+
+/// Statistics of the VP8 encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LossyStats {
+    pub intra4_blocks: u32,
+    pub intra16_blocks: u32,
+    pub skipped_blocks: u32,
+    /// Approximate bytes spent on the frame header.
+    pub header_bytes: usize,
+    /// Approximate bytes spent on the mode partition (#0).
+    pub mode_partition_bytes: usize,
+    pub segments: [SegmentStats; 4],
+    /// Size of the enhancement layer data.
+    pub layer_data_size: usize,
+}
+
+impl LossyStats {
+    /// Approximate bytes spent on coefficients over all segments.
+    ///
+    /// libwebp only counts these with `method` 0-2; the token-buffer paths of
+    /// the higher methods leave them at 0.
+    pub fn residual_bytes(&self) -> usize {
+        self.segments
+            .iter()
+            .map(|s| s.dc_bytes + s.ac_bytes + s.uv_bytes)
+            .sum()
+    }
+}
+
+/// Statistics of one of the four VP8 segments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SegmentStats {
+    /// Number of macroblocks in the segment.
+    pub macroblocks: u32,
+    pub quantizer: i32,
+    /// Filter strength, 0-63.
+    pub filter_level: i32,
+    /// Approximate bytes spent on luma DC coefficients.
+    pub dc_bytes: usize,
+    /// Approximate bytes spent on luma AC coefficients.
+    pub ac_bytes: usize,
+    /// Approximate bytes spent on chroma coefficients.
+    pub uv_bytes: usize,
+}
+
+/// Statistics of the VP8L encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LosslessStats {
+    pub transforms: LosslessTransforms,
+    /// Precision bits of the entropy image.
+    pub histogram_bits: i32,
+    /// Precision bits of the predictor transform.
+    pub transform_bits: i32,
+    /// Bits of the color cache, 0 if unused.
+    pub cache_bits: i32,
+    /// Number of palette colors, 0 if unused.
+    pub palette_size: u32,
+    /// Size of the lossless bitstream.
+    pub size: usize,
+    /// Size of the header (transforms, Huffman codes, etc.).
+    #[cfg(libwebp_0_5)]
+    #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "0_5")))]
+    pub header_size: usize,
+    /// Size of the image data.
+    #[cfg(libwebp_0_5)]
+    #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "0_5")))]
+    pub data_size: usize,
+    /// Precision bits of the cross-color transform.
+    #[cfg(libwebp_1_5)]
+    #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "1_5")))]
+    pub cross_color_transform_bits: i32,
+}
+
+/// The transforms used by the lossless encoder (`lossless_features`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LosslessTransforms {
+    pub predictor: bool,
+    pub cross_color: bool,
+    pub subtract_green: bool,
+    /// Color indexing.
+    pub palette: bool,
+}
+
+impl LosslessTransforms {
+    pub fn from_bits(bits: u32) -> LosslessTransforms {
+        LosslessTransforms {
+            predictor: bits & 1 != 0,
+            cross_color: bits & 2 != 0,
+            subtract_green: bits & 4 != 0,
+            palette: bits & 8 != 0,
+        }
+    }
+}
+
+fn size(value: i32) -> usize {
+    value.max(0) as usize
+}
+
+impl From<&WebPAuxStats> for EncodeStats {
+    fn from(raw: &WebPAuxStats) -> EncodeStats {
+        let blocks = raw.block_count;
+        let lossy = if blocks.iter().any(|&n| n > 0) {
+            let mut segments = [SegmentStats::default(); 4];
+            for (i, segment) in segments.iter_mut().enumerate() {
+                *segment = SegmentStats {
+                    macroblocks: raw.segment_size[i].max(0) as u32,
+                    quantizer: raw.segment_quant[i],
+                    filter_level: raw.segment_level[i],
+                    dc_bytes: size(raw.residual_bytes[0][i]),
+                    ac_bytes: size(raw.residual_bytes[1][i]),
+                    uv_bytes: size(raw.residual_bytes[2][i]),
+                };
+            }
+            Some(LossyStats {
+                intra4_blocks: blocks[0].max(0) as u32,
+                intra16_blocks: blocks[1].max(0) as u32,
+                skipped_blocks: blocks[2].max(0) as u32,
+                header_bytes: size(raw.header_bytes[0]),
+                mode_partition_bytes: size(raw.header_bytes[1]),
+                segments,
+                layer_data_size: size(raw.layer_data_size),
+            })
+        } else {
+            None
+        };
+        let lossless = if raw.lossless_size > 0 {
+            Some(LosslessStats {
+                transforms: LosslessTransforms::from_bits(raw.lossless_features),
+                histogram_bits: raw.histogram_bits,
+                transform_bits: raw.transform_bits,
+                cache_bits: raw.cache_bits,
+                palette_size: raw.palette_size.max(0) as u32,
+                size: size(raw.lossless_size),
+                #[cfg(libwebp_0_5)]
+                header_size: size(raw.lossless_hdr_size),
+                #[cfg(libwebp_0_5)]
+                data_size: size(raw.lossless_data_size),
+                #[cfg(libwebp_1_5)]
+                cross_color_transform_bits: raw.cross_color_transform_bits,
+            })
+        } else {
+            None
+        };
+        EncodeStats {
+            coded_size: size(raw.coded_size),
+            psnr: PsnrStats {
+                y: raw.PSNR[0],
+                u: raw.PSNR[1],
+                v: raw.PSNR[2],
+                all: raw.PSNR[3],
+                alpha: raw.PSNR[4],
+            },
+            alpha_size: size(raw.alpha_data_size),
+            lossy,
+            lossless,
+        }
+    }
+}
+
+impl Picture {
+    /// Encodes the picture and collects statistics.
+    pub fn encode_with_stats(
+        &mut self,
+        config: &EncoderConfig,
+    ) -> Result<(Vec<u8>, EncodeStats), EncodeError> {
+        let mut output = Vec::new();
+        let mut raw: WebPAuxStats = unsafe { std::mem::zeroed() };
+        self.encode_raw(config, &mut output, &mut raw)?;
+        Ok((output, EncodeStats::from(&raw)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::tests::gradient;
+
+    fn picture(alpha: u8) -> Picture {
+        Picture::from_rgba(&gradient(48, 48, alpha), 48, 48).unwrap()
+    }
+
+    #[test]
+    fn test_lossy_stats() {
+        let mut config = EncoderConfig::lossy(80.0).unwrap();
+        config.set_method(2);
+        let (webp, stats) = picture(128).encode_with_stats(&config).unwrap();
+        assert_eq!(stats.coded_size, webp.len());
+        assert!(stats.alpha_size > 0);
+        assert!(stats.psnr.y > 20.0);
+        let lossy = stats.lossy.unwrap();
+        assert_eq!(
+            lossy.intra4_blocks + lossy.intra16_blocks,
+            3 * 3,
+            "{:?}",
+            lossy
+        );
+        let macroblocks: u32 = lossy.segments.iter().map(|s| s.macroblocks).sum();
+        assert_eq!(macroblocks, 9);
+        assert!(lossy.residual_bytes() > 0);
+        assert!(lossy.header_bytes > 0);
+    }
+
+    #[test]
+    fn test_lossless_stats() {
+        let (webp, stats) = picture(255)
+            .encode_with_stats(&EncoderConfig::lossless(75.0).unwrap())
+            .unwrap();
+        assert_eq!(stats.coded_size, webp.len());
+        assert!(stats.lossy.is_none());
+        let lossless = stats.lossless.unwrap();
+        assert!(lossless.size > 0 && lossless.size <= webp.len());
+        assert!(lossless.transforms.predictor || lossless.transforms.subtract_green);
+    }
+
+    #[test]
+    fn test_transform_bits() {
+        let transforms = LosslessTransforms::from_bits(0b1010);
+        assert!(!transforms.predictor && transforms.cross_color);
+        assert!(!transforms.subtract_green && transforms.palette);
+    }
+}
@@ -0,0 +1,244 @@
+//! Re-encoding of animations.
+//!
+//! [`transcode_animation`] decodes every canvas with [`AnimDecoder`] and
+//! encodes it again with [`AnimEncoder`], keeping the frame timestamps, the
+//! loop count, the background color and the ICC, EXIF and XMP chunks.
+
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::os::raw::*;
+use std::ptr;
+use std::slice;
+use std::time::Duration;
+
+use crate::anim::AnimDecoder;
+use crate::anim_encoder::{AnimEncodeError, AnimEncoder, AnimEncoderOptions};
+use crate::decoder::{DecodeError, Limits};
+use crate::demux::*;
+use crate::encoder::{EncoderConfig, Picture};
+use crate::mux::*;
+use crate::mux_types::*;
+
+/// The metadata chunks carried over.
+const METADATA: [&[u8; 4]; 3] = [b"ICCP", b"EXIF", b"XMP "];
+
+/// Errors of [`transcode_animation`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranscodeError {
+    Decode(DecodeError),
+    Encode(AnimEncodeError),
+    /// Re-attaching the metadata failed.
+    Mux(WebPMuxError),
+}
+
+impl fmt::Display for TranscodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TranscodeError::Decode(e) => e.fmt(f),
+            TranscodeError::Encode(e) => e.fmt(f),
+            TranscodeError::Mux(code) => write!(f, "mux error {}", code),
+        }
+    }
+}
+
+impl Error for TranscodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TranscodeError::Decode(e) => Some(e),
+            TranscodeError::Encode(e) => Some(e),
+            TranscodeError::Mux(_) => None,
+        }
+    }
+}
+
+impl From<DecodeError> for TranscodeError {
+    fn from(e: DecodeError) -> TranscodeError {
+        TranscodeError::Decode(e)
+    }
+}
+
+impl From<AnimEncodeError> for TranscodeError {
+    fn from(e: AnimEncodeError) -> TranscodeError {
+        TranscodeError::Encode(e)
+    }
+}
+
+/// Re-encodes an animation with the default [`Limits`] and
+/// [`AnimEncoderOptions`].
+///
+/// `per_frame_config` is called with the index and start time of each
+/// frame and returns its encoding parameters.
+pub fn transcode_animation<F>(data: &[u8], per_frame_config: F) -> Result<Vec<u8>, TranscodeError>
+where
+    F: FnMut(u32, Duration) -> EncoderConfig,
+{
+    let options = AnimEncoderOptions::new()?;
+    transcode_animation_with(data, Limits::default(), &options, per_frame_config)
+}
+
+/// Like [`transcode_animation`], decoding within `limits` and encoding with
+/// `options`. The loop count and background color of `options` are replaced
+/// by those of the input.
+pub fn transcode_animation_with<F>(
+    data: &[u8],
+    limits: Limits,
+    options: &AnimEncoderOptions,
+    mut per_frame_config: F,
+) -> Result<Vec<u8>, TranscodeError>
+where
+    F: FnMut(u32, Duration) -> EncoderConfig,
+{
+    let mut decoder = AnimDecoder::with_limits(data, limits)?;
+    let info = decoder.info();
+    let mut options = *options;
+    options
+        .set_loop_count(info.loop_count)
+        .set_bgcolor(info.bgcolor);
+    let mut encoder = AnimEncoder::new(info.canvas_width, info.canvas_height, &options)?;
+
+    let mut start = Duration::from_millis(0);
+    let mut index = 0;
+    while let Some(frame) = decoder.next_frame()? {
+        let mut picture = Picture::from_rgba(frame.data, info.canvas_width, info.canvas_height)
+            .map_err(AnimEncodeError::from)?;
+        let config = per_frame_config(index, start);
+        encoder.add_frame(&mut picture, start, &config)?;
+        start = frame.timestamp;
+        index += 1;
+    }
+    let output = encoder.finish(start)?;
+
+    let chunks: Vec<_> = METADATA
+        .iter()
+        .filter_map(|fourcc| get_chunk(data, fourcc).map(|chunk| (*fourcc, chunk)))
+        .collect();
+    if chunks.is_empty() {
+        return Ok(output);
+    }
+    set_chunks(&output, &chunks).map_err(TranscodeError::Mux)
+}
+
+/// The first chunk `fourcc` of `data`.
+fn get_chunk<'a>(data: &'a [u8], fourcc: &[u8; 4]) -> Option<&'a [u8]> {
+    let webp_data = WebPData {
+        bytes: data.as_ptr(),
+        size: data.len(),
+    };
+    unsafe {
+        let demux = WebPDemux(&webp_data);
+        if demux.is_null() {
+            return None;
+        }
+        let mut iter: WebPChunkIterator = mem::zeroed();
+        let chunk = if WebPDemuxGetChunk(demux, fourcc.as_ptr() as *const c_char, 1, &mut iter) != 0
+        {
+            // The chunk points into `data`, not into the demuxer.
+            let chunk = slice::from_raw_parts(iter.chunk.bytes, iter.chunk.size);
+            WebPDemuxReleaseChunkIterator(&mut iter);
+            Some(chunk)
+        } else {
+            None
+        };
+        WebPDemuxDelete(demux);
+        chunk
+    }
+}
+
+/// Sets the chunks on `data` and reassembles it.
+pub(crate) fn set_chunks(
+    data: &[u8],
+    chunks: &[(&[u8; 4], &[u8])],
+) -> Result<Vec<u8>, WebPMuxError> {
+    let webp_data = WebPData {
+        bytes: data.as_ptr(),
+        size: data.len(),
+    };
+    unsafe {
+        let mux = WebPMuxCreate(&webp_data, 0);
+        if mux.is_null() {
+            return Err(WEBP_MUX_BAD_DATA);
+        }
+        let result = (|| {
+            for (fourcc, chunk) in chunks {
+                let chunk = WebPData {
+                    bytes: chunk.as_ptr(),
+                    size: chunk.len(),
+                };
+                let err = WebPMuxSetChunk(mux, fourcc.as_ptr() as *const c_char, &chunk, 1);
+                if err != WEBP_MUX_OK {
+                    return Err(err);
+                }
+            }
+            let mut assembled = WebPData {
+                bytes: ptr::null(),
+                size: 0,
+            };
+            let err = WebPMuxAssemble(mux, &mut assembled);
+            if err != WEBP_MUX_OK {
+                return Err(err);
+            }
+            let output = slice::from_raw_parts(assembled.bytes, assembled.size).to_vec();
+            WebPDataClear(&mut assembled);
+            Ok(output)
+        })();
+        WebPMuxDelete(mux);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(data: &[u8]) -> Vec<(Vec<u8>, Duration)> {
+        let mut decoder = AnimDecoder::new(data).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.next_frame().unwrap() {
+            frames.push((frame.data.to_vec(), frame.timestamp));
+        }
+        frames
+    }
+
+    #[test]
+    fn test_transcode() {
+        let source = std::fs::read("./tests/animated.webp").unwrap();
+        let source = set_chunks(
+            &source,
+            &[(b"ICCP", b"icc profile"), (b"XMP ", b"<x:xmpmeta/>")],
+        )
+        .unwrap();
+        let mut calls = Vec::new();
+        let output = transcode_animation(&source, |index, start| {
+            calls.push((index, start));
+            EncoderConfig::lossless(10.0).unwrap()
+        })
+        .unwrap();
+
+        let before = AnimDecoder::new(&source).unwrap().info();
+        let after = AnimDecoder::new(&output).unwrap().info();
+        assert_eq!(after.loop_count, before.loop_count);
+        assert_eq!(after.bgcolor, before.bgcolor);
+        assert_eq!(after.duration, before.duration);
+        assert_eq!(calls.len() as u32, before.frame_count);
+        assert_eq!(calls[0], (0, Duration::from_millis(0)));
+
+        let (before, after) = (frames(&source), frames(&output));
+        assert_eq!(before.len(), after.len());
+        for (a, b) in before.iter().zip(&after) {
+            assert_eq!(a.1, b.1);
+            assert!(a.0 == b.0);
+        }
+        assert_eq!(get_chunk(&output, b"ICCP"), Some(&b"icc profile"[..]));
+        assert_eq!(get_chunk(&output, b"XMP "), Some(&b"<x:xmpmeta/>"[..]));
+        assert_eq!(get_chunk(&output, b"EXIF"), None);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(matches!(
+            transcode_animation(b"RIFF", |_, _| EncoderConfig::new().unwrap()),
+            Err(TranscodeError::Decode(_))
+        ));
+    }
+}
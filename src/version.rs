@@ -0,0 +1,362 @@
+//! Runtime compatibility check between the compiled bindings and the linked
+//! libwebp.
+//!
+//! The struct layouts and the `WEBP_*_ABI_VERSION` constants in this crate
+//! are selected at compile time through the `0_5`/.../`1_5` features.
+//! When a system libwebp is linked through pkg-config, nothing guarantees that
+//! it is at least as new as the API level we were compiled for. [`check`]
+//! queries the version getters of every linked library and compares them
+//! against the compile-time ABI versions.
+
+use std::error::Error;
+use std::fmt;
+use std::os::raw::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::decode::*;
+#[cfg(feature = "demux")]
+use crate::demux::*;
+use crate::encode::*;
+#[cfg(feature = "mux")]
+use crate::mux::*;
+use crate::types::WEBP_ABI_IS_INCOMPATIBLE;
+
+/// A libwebp version, as returned by the `WebPGet*Version` functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u8,
+    pub minor: u8,
+    pub revision: u8,
+}
+
+impl Version {
+    pub const fn new(major: u8, minor: u8, revision: u8) -> Version {
+        Version {
+            major,
+            minor,
+            revision,
+        }
+    }
+
+    /// Decodes a version packed in hexadecimal using 8 bits for each of
+    /// major/minor/revision. E.g: v2.5.7 is 0x020507.
+    pub fn from_packed(packed: c_int) -> Version {
+        Version {
+            major: ((packed >> 16) & 0xFF) as u8,
+            minor: ((packed >> 8) & 0xFF) as u8,
+            revision: (packed & 0xFF) as u8,
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.revision)
+    }
+}
+
+/// One of the libwebp libraries whose version can be queried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Library {
+    Decoder,
+    Encoder,
+    #[cfg(feature = "demux")]
+    #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "demux")))]
+    Demux,
+    #[cfg(feature = "mux")]
+    #[cfg_attr(feature = "__doc_cfg", doc(cfg(feature = "mux")))]
+    Mux,
+}
+
+impl Library {
+    /// The ABI version this crate was compiled against.
+    pub fn compiled_abi_version(self) -> c_int {
+        match self {
+            Library::Decoder => WEBP_DECODER_ABI_VERSION,
+            Library::Encoder => WEBP_ENCODER_ABI_VERSION,
+            #[cfg(feature = "demux")]
+            Library::Demux => WEBP_DEMUX_ABI_VERSION,
+            #[cfg(feature = "mux")]
+            Library::Mux => WEBP_MUX_ABI_VERSION,
+        }
+    }
+
+    /// The version of the library actually linked.
+    pub fn linked_version(self) -> Version {
+        let packed = unsafe {
+            match self {
+                Library::Decoder => WebPGetDecoderVersion(),
+                Library::Encoder => WebPGetEncoderVersion(),
+                #[cfg(feature = "demux")]
+                Library::Demux => WebPGetDemuxVersion(),
+                #[cfg(feature = "mux")]
+                Library::Mux => WebPGetMuxVersion(),
+            }
+        };
+        Version::from_packed(packed)
+    }
+
+    /// The ABI version declared by the headers of the given libwebp release.
+    pub fn abi_version_of(self, version: Version) -> c_int {
+        let at_least = |major, minor| version >= Version::new(major, minor, 0);
+        match self {
+            Library::Decoder => {
+                if at_least(1, 4) {
+                    0x0210
+                } else if at_least(1, 1) {
+                    0x0209
+                } else if at_least(0, 5) {
+                    0x0208
+                } else {
+                    0x0203
+                }
+            }
+            Library::Encoder => {
+                if at_least(1, 5) {
+                    0x0210
+                } else if at_least(1, 1) {
+                    0x020F
+                } else if at_least(0, 6) {
+                    0x020E
+                } else if at_least(0, 5) {
+                    0x0209
+                } else {
+                    0x0202
+                }
+            }
+            #[cfg(feature = "demux")]
+            Library::Demux => {
+                if at_least(0, 5) {
+                    0x0107
+                } else {
+                    0x0101
+                }
+            }
+            #[cfg(feature = "mux")]
+            Library::Mux => {
+                if at_least(0, 6) {
+                    0x0108
+                } else if at_least(0, 5) {
+                    0x0106
+                } else {
+                    0x0101
+                }
+            }
+        }
+    }
+
+    fn all() -> Vec<Library> {
+        vec![
+            Library::Decoder,
+            Library::Encoder,
+            #[cfg(feature = "demux")]
+            Library::Demux,
+            #[cfg(feature = "mux")]
+            Library::Mux,
+        ]
+    }
+}
+
+impl fmt::Display for Library {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Library::Decoder => "libwebp decoder",
+            Library::Encoder => "libwebp encoder",
+            #[cfg(feature = "demux")]
+            Library::Demux => "libwebpdemux",
+            #[cfg(feature = "mux")]
+            Library::Mux => "libwebpmux",
+        })
+    }
+}
+
+/// Compatibility status of a single linked library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LibraryStatus {
+    pub library: Library,
+    /// Version reported by the linked library.
+    pub linked_version: Version,
+    /// ABI version corresponding to `linked_version`.
+    pub linked_abi_version: c_int,
+    /// ABI version the bindings were compiled against.
+    pub compiled_abi_version: c_int,
+}
+
+impl LibraryStatus {
+    /// True if the major ABI revisions differ (`WEBP_ABI_IS_INCOMPATIBLE`).
+    pub fn is_abi_incompatible(&self) -> bool {
+        WEBP_ABI_IS_INCOMPATIBLE(
+            self.linked_abi_version as u16,
+            self.compiled_abi_version as u16,
+        )
+    }
+
+    /// True if the linked library predates the API level we were compiled
+    /// for. This alone is not an incompatibility: the functions it lacks are
+    /// missing at link time, or with `dynamic` when they are called.
+    pub fn is_too_old(&self) -> bool {
+        self.linked_abi_version < self.compiled_abi_version
+    }
+
+    /// True if the linked library fills in a larger `WebPConfig` than the
+    /// compiled one. It grew by one word in 0.6, and libwebp initializes all
+    /// of it whatever ABI version the caller passes.
+    pub fn is_config_too_small(&self) -> bool {
+        self.library == Library::Encoder
+            && self.linked_version >= Version::new(0, 6, 0)
+            && self.compiled_abi_version < Library::Encoder.abi_version_of(Version::new(0, 6, 0))
+    }
+
+    pub fn is_compatible(&self) -> bool {
+        !self.is_abi_incompatible() && !self.is_config_too_small()
+    }
+}
+
+/// Result of [`check`]: the status of every linked library.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub libraries: Vec<LibraryStatus>,
+}
+
+impl Report {
+    pub fn is_compatible(&self) -> bool {
+        self.libraries.iter().all(LibraryStatus::is_compatible)
+    }
+
+    /// Libraries that failed the check.
+    pub fn mismatches(&self) -> impl Iterator<Item = &LibraryStatus> {
+        self.libraries
+            .iter()
+            .filter(|status| !status.is_compatible())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+        for status in self.mismatches() {
+            if !first {
+                f.write_str("; ")?;
+            }
+            first = false;
+            if status.is_config_too_small() {
+                write!(
+                    f,
+                    "{} {} has a larger WebPConfig than the bindings compiled for ABI version {:#06x}",
+                    status.library, status.linked_version, status.compiled_abi_version,
+                )?;
+                continue;
+            }
+            write!(
+                f,
+                "{} {} has ABI version {:#06x}, but the bindings were compiled for {:#06x}",
+                status.library,
+                status.linked_version,
+                status.linked_abi_version,
+                status.compiled_abi_version,
+            )?;
+        }
+        if first {
+            f.write_str("all linked libwebp libraries are compatible")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for Report {}
+
+/// Reads the versions of the linked libraries and compares them against the
+/// compile-time ABI versions.
+pub fn check() -> Report {
+    let libraries = Library::all()
+        .into_iter()
+        .map(|library| {
+            let linked_version = library.linked_version();
+            LibraryStatus {
+                library,
+                linked_version,
+                linked_abi_version: library.abi_version_of(linked_version),
+                compiled_abi_version: library.compiled_abi_version(),
+            }
+        })
+        .collect();
+    Report { libraries }
+}
+
+const UNCHECKED: usize = 0;
+const COMPATIBLE: usize = 1;
+const INCOMPATIBLE: usize = 2;
+
+static STATE: AtomicUsize = AtomicUsize::new(UNCHECKED);
+
+/// Same as [`check`], but only queries the libraries once per process and
+/// returns the report as an error if any library is incompatible.
+pub fn ensure() -> Result<(), Report> {
+    match STATE.load(Ordering::Relaxed) {
+        COMPATIBLE => Ok(()),
+        INCOMPATIBLE => Err(check()),
+        _ => {
+            let report = check();
+            if report.is_compatible() {
+                STATE.store(COMPATIBLE, Ordering::Relaxed);
+                Ok(())
+            } else {
+                STATE.store(INCOMPATIBLE, Ordering::Relaxed);
+                Err(report)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_packed() {
+        assert_eq!(Version::from_packed(0x020507), Version::new(2, 5, 7));
+        assert_eq!(Version::from_packed(0x010200).to_string(), "1.2.0");
+    }
+
+    #[test]
+    fn test_abi_version_of() {
+        assert_eq!(
+            Library::Encoder.abi_version_of(Version::new(0, 6, 1)),
+            0x020E
+        );
+        assert_eq!(
+            Library::Decoder.abi_version_of(Version::new(1, 2, 0)),
+            0x0209
+        );
+    }
+
+    #[test]
+    fn test_is_compatible() {
+        let status = |linked, compiled| LibraryStatus {
+            library: Library::Encoder,
+            linked_version: linked,
+            linked_abi_version: Library::Encoder.abi_version_of(linked),
+            compiled_abi_version: Library::Encoder.abi_version_of(compiled),
+        };
+        let v = Version::new;
+        assert!(status(v(1, 3, 1), v(0, 6, 0)).is_compatible());
+        assert!(status(v(1, 3, 1), v(0, 5, 0)).is_config_too_small());
+        assert!(!status(v(1, 3, 1), v(0, 5, 0)).is_compatible());
+        assert!(status(v(0, 5, 2), v(0, 5, 0)).is_compatible());
+        assert!(status(v(1, 3, 1), v(1, 5, 0)).is_too_old());
+        assert!(status(v(1, 3, 1), v(1, 5, 0)).is_compatible());
+        let next_major = LibraryStatus {
+            linked_abi_version: 0x0300,
+            ..status(v(1, 3, 1), v(1, 2, 0))
+        };
+        assert!(next_major.is_abi_incompatible());
+        assert!(!next_major.is_compatible());
+    }
+
+    #[test]
+    fn test_check() {
+        let report = check();
+        assert!(report.is_compatible(), "{}", report);
+        assert!(ensure().is_ok());
+    }
+}
@@ -0,0 +1,104 @@
+//! C allocator for the bundled libwebp on `wasm32-unknown-unknown`.
+//!
+//! That target has no C library, so `wasm-shim/stdlib.h` routes `malloc()`
+//! and friends (and therefore `WebPMalloc()`/`WebPFree()`) to the Rust global
+//! allocator through these functions.
+
+use std::alloc::{alloc, alloc_zeroed, dealloc, realloc, Layout};
+use std::os::raw::c_void;
+use std::ptr;
+
+// The allocation size is stored in front of every block, which also keeps the
+// returned pointer aligned as malloc() requires.
+const HEADER: usize = 16;
+
+unsafe fn allocate(size: usize, zeroed: bool) -> *mut c_void {
+    let total = match size.checked_add(HEADER) {
+        Some(total) => total,
+        None => return ptr::null_mut(),
+    };
+    let layout = match Layout::from_size_align(total, HEADER) {
+        Ok(layout) => layout,
+        Err(_) => return ptr::null_mut(),
+    };
+    let block = if zeroed {
+        alloc_zeroed(layout)
+    } else {
+        alloc(layout)
+    };
+    if block.is_null() {
+        return ptr::null_mut();
+    }
+    *(block as *mut usize) = total;
+    block.add(HEADER) as *mut c_void
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_libwebp_sys_wasm_shim_malloc(size: usize) -> *mut c_void {
+    allocate(size, false)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_libwebp_sys_wasm_shim_calloc(
+    nmemb: usize,
+    size: usize,
+) -> *mut c_void {
+    match nmemb.checked_mul(size) {
+        Some(size) => allocate(size, true),
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_libwebp_sys_wasm_shim_realloc(
+    ptr: *mut c_void,
+    size: usize,
+) -> *mut c_void {
+    if ptr.is_null() {
+        return allocate(size, false);
+    }
+    let total = match size.checked_add(HEADER) {
+        Some(total) => total,
+        None => return ptr::null_mut(),
+    };
+    let block = (ptr as *mut u8).sub(HEADER);
+    let layout = Layout::from_size_align_unchecked(*(block as *mut usize), HEADER);
+    let block = realloc(block, layout, total);
+    if block.is_null() {
+        return ptr::null_mut();
+    }
+    *(block as *mut usize) = total;
+    block.add(HEADER) as *mut c_void
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_libwebp_sys_wasm_shim_free(ptr: *mut c_void) {
+    if ptr.is_null() {
+        return;
+    }
+    let block = (ptr as *mut u8).sub(HEADER);
+    let layout = Layout::from_size_align_unchecked(*(block as *mut usize), HEADER);
+    dealloc(block, layout);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alloc_roundtrip() {
+        unsafe {
+            let p = rust_libwebp_sys_wasm_shim_calloc(4, 8) as *mut u8;
+            assert!(!p.is_null());
+            assert_eq!(p as usize % HEADER, 0);
+            assert!((0..32).all(|i| *p.add(i) == 0));
+            *p.add(31) = 42;
+            let p = rust_libwebp_sys_wasm_shim_realloc(p as *mut c_void, 1024) as *mut u8;
+            assert!(!p.is_null());
+            assert_eq!(*p.add(31), 42);
+            rust_libwebp_sys_wasm_shim_free(p as *mut c_void);
+            rust_libwebp_sys_wasm_shim_free(ptr::null_mut());
+            assert!(rust_libwebp_sys_wasm_shim_calloc(!0, 2).is_null());
+        }
+    }
+}
@@ -0,0 +1,7 @@
+/* Minimal <assert.h> for wasm32-unknown-unknown. */
+#ifndef LIBWEBP_SYS_WASM_SHIM_ASSERT_H
+#define LIBWEBP_SYS_WASM_SHIM_ASSERT_H
+
+#define assert(expr) ((void)0)
+
+#endif  // LIBWEBP_SYS_WASM_SHIM_ASSERT_H
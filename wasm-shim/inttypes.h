@@ -0,0 +1,8 @@
+/* Minimal <inttypes.h> for wasm32-unknown-unknown. libwebp only needs the
+ * fixed-width integer types. */
+#ifndef LIBWEBP_SYS_WASM_SHIM_INTTYPES_H
+#define LIBWEBP_SYS_WASM_SHIM_INTTYPES_H
+
+#include <stdint.h>
+
+#endif  // LIBWEBP_SYS_WASM_SHIM_INTTYPES_H
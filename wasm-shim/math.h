@@ -0,0 +1,21 @@
+/* Minimal <math.h> for wasm32-unknown-unknown. The functions are provided by
+ * Rust's compiler-builtins. */
+#ifndef LIBWEBP_SYS_WASM_SHIM_MATH_H
+#define LIBWEBP_SYS_WASM_SHIM_MATH_H
+
+double ceil(double x);
+double exp(double x);
+double fabs(double x);
+double floor(double x);
+double log(double x);
+double log10(double x);
+double pow(double x, double y);
+double sqrt(double x);
+float ceilf(float x);
+float expf(float x);
+float floorf(float x);
+float logf(float x);
+float powf(float x, float y);
+float sqrtf(float x);
+
+#endif  // LIBWEBP_SYS_WASM_SHIM_MATH_H
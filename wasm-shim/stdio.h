@@ -0,0 +1,35 @@
+/* Minimal <stdio.h> for wasm32-unknown-unknown. There is no output stream;
+ * snprintf() only copies the format string, which is enough for the error
+ * messages of WebPAnimEncoder. */
+#ifndef LIBWEBP_SYS_WASM_SHIM_STDIO_H
+#define LIBWEBP_SYS_WASM_SHIM_STDIO_H
+
+#include <stddef.h>
+
+typedef struct libwebp_sys_wasm_shim_file FILE;
+
+#define stdout ((FILE*)0)
+#define stderr ((FILE*)0)
+
+static inline int printf(const char* format, ...) {
+  (void)format;
+  return 0;
+}
+
+static inline int fprintf(FILE* stream, const char* format, ...) {
+  (void)stream;
+  (void)format;
+  return 0;
+}
+
+static inline int snprintf(char* str, size_t size, const char* format, ...) {
+  size_t len = 0;
+  while (format[len] != '\0') {
+    if (len + 1 < size) str[len] = format[len];
+    ++len;
+  }
+  if (size > 0) str[len < size ? len : size - 1] = '\0';
+  return (int)len;
+}
+
+#endif  // LIBWEBP_SYS_WASM_SHIM_STDIO_H
@@ -0,0 +1,94 @@
+/* Minimal <stdlib.h> for building libwebp on wasm32-unknown-unknown, which
+ * has no C library. The allocator is implemented in src/wasm_shim.rs. */
+#ifndef LIBWEBP_SYS_WASM_SHIM_STDLIB_H
+#define LIBWEBP_SYS_WASM_SHIM_STDLIB_H
+
+#include <stddef.h>
+#include <string.h>
+
+void* rust_libwebp_sys_wasm_shim_malloc(size_t size);
+void* rust_libwebp_sys_wasm_shim_calloc(size_t nmemb, size_t size);
+void* rust_libwebp_sys_wasm_shim_realloc(void* ptr, size_t size);
+void rust_libwebp_sys_wasm_shim_free(void* ptr);
+
+static inline void* malloc(size_t size) {
+  return rust_libwebp_sys_wasm_shim_malloc(size);
+}
+
+static inline void* calloc(size_t nmemb, size_t size) {
+  return rust_libwebp_sys_wasm_shim_calloc(nmemb, size);
+}
+
+static inline void* realloc(void* ptr, size_t size) {
+  return rust_libwebp_sys_wasm_shim_realloc(ptr, size);
+}
+
+static inline void free(void* ptr) { rust_libwebp_sys_wasm_shim_free(ptr); }
+
+static inline void abort(void) { __builtin_trap(); }
+
+static inline int abs(int x) { return x < 0 ? -x : x; }
+
+static inline char* getenv(const char* name) {
+  (void)name;
+  return NULL;
+}
+
+static inline void* bsearch(const void* key, const void* base, size_t nmemb,
+                            size_t size,
+                            int (*compar)(const void*, const void*)) {
+  const char* lo = (const char*)base;
+  while (nmemb > 0) {
+    const char* const mid = lo + (nmemb / 2) * size;
+    const int cmp = compar(key, mid);
+    if (cmp == 0) return (void*)mid;
+    if (cmp > 0) {
+      lo = mid + size;
+      nmemb -= nmemb / 2 + 1;
+    } else {
+      nmemb /= 2;
+    }
+  }
+  return NULL;
+}
+
+static inline void libwebp_sys_wasm_shim_swap(char* a, char* b, size_t size) {
+  while (size-- > 0) {
+    const char tmp = *a;
+    *a++ = *b;
+    *b++ = tmp;
+  }
+}
+
+static inline void libwebp_sys_wasm_shim_sift_down(
+    char* base, size_t root, size_t nmemb, size_t size,
+    int (*compar)(const void*, const void*)) {
+  for (;;) {
+    size_t child = 2 * root + 1;
+    if (child >= nmemb) return;
+    if (child + 1 < nmemb &&
+        compar(base + child * size, base + (child + 1) * size) < 0) {
+      ++child;
+    }
+    if (compar(base + root * size, base + child * size) >= 0) return;
+    libwebp_sys_wasm_shim_swap(base + root * size, base + child * size, size);
+    root = child;
+  }
+}
+
+// Heapsort: not stable, like most qsort implementations.
+static inline void qsort(void* base, size_t nmemb, size_t size,
+                         int (*compar)(const void*, const void*)) {
+  char* const b = (char*)base;
+  size_t i;
+  if (nmemb < 2) return;
+  for (i = nmemb / 2; i-- > 0;) {
+    libwebp_sys_wasm_shim_sift_down(b, i, nmemb, size, compar);
+  }
+  for (i = nmemb - 1; i > 0; --i) {
+    libwebp_sys_wasm_shim_swap(b, b + i * size, size);
+    libwebp_sys_wasm_shim_sift_down(b, 0, i, size, compar);
+  }
+}
+
+#endif  // LIBWEBP_SYS_WASM_SHIM_STDLIB_H
@@ -0,0 +1,31 @@
+/* Minimal <string.h> for wasm32-unknown-unknown. The mem* functions are
+ * provided by Rust's compiler-builtins. */
+#ifndef LIBWEBP_SYS_WASM_SHIM_STRING_H
+#define LIBWEBP_SYS_WASM_SHIM_STRING_H
+
+#include <stddef.h>
+
+void* memcpy(void* dest, const void* src, size_t n);
+void* memmove(void* dest, const void* src, size_t n);
+void* memset(void* s, int c, size_t n);
+int memcmp(const void* s1, const void* s2, size_t n);
+
+static inline size_t strlen(const char* s) {
+  size_t len = 0;
+  while (s[len] != '\0') ++len;
+  return len;
+}
+
+static inline int strncmp(const char* s1, const char* s2, size_t n) {
+  for (; n > 0; ++s1, ++s2, --n) {
+    if (*s1 != *s2) return (unsigned char)*s1 - (unsigned char)*s2;
+    if (*s1 == '\0') return 0;
+  }
+  return 0;
+}
+
+static inline int strcmp(const char* s1, const char* s2) {
+  return strncmp(s1, s2, (size_t)-1);
+}
+
+#endif  // LIBWEBP_SYS_WASM_SHIM_STRING_H